@@ -0,0 +1,21 @@
+//! Generates the C header `include/mamba.h` when building with the `capi` feature
+
+fn main() {
+    #[cfg(feature = "capi")]
+    generate_header();
+}
+
+#[cfg(feature = "capi")]
+fn generate_header() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir))
+        .expect("Could not read cbindgen.toml");
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("Unable to generate C header")
+        .write_to_file(format!("{}/include/mamba.h", crate_dir));
+    println!("cargo:rerun-if-changed=src/mambalib/capi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+}
@@ -0,0 +1,103 @@
+/* C API of mamba-rs, generated by cbindgen, do not edit. */
+
+#ifndef MAMBA_H
+#define MAMBA_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/*
+ Bond predictor keeping the xgboost model loaded
+ */
+typedef struct MambaPredictor MambaPredictor;
+
+/*
+ Perceived bonds, formal charges and radicals of a molecule
+ */
+typedef struct MambaResult MambaResult;
+
+/*
+ Message of the last error on this thread, valid until the next failing call
+ */
+const char *mamba_last_error(void);
+
+/*
+ Loads the model, returns NULL on failure.
+
+ # Safety
+ `model_path` must be a valid null terminated string.
+ */
+struct MambaPredictor *mamba_predictor_new(const char *model_path);
+
+/*
+ Frees a predictor created by `mamba_predictor_new`.
+
+ # Safety
+ `predictor` must be NULL or returned by `mamba_predictor_new` and not freed before.
+ */
+void mamba_predictor_free(struct MambaPredictor *predictor);
+
+/*
+ Perceives the bonds of a molecule with `natoms` element symbols and
+ `3 * natoms` coordinates in Angstrom, returns NULL on failure, e.g. for a
+ NULL element symbol.
+
+ # Safety
+ `predictor` must be a valid predictor, `elements` must point to `natoms`
+ null terminated strings and `coords` to `3 * natoms` floats.
+ */
+struct MambaResult *mamba_perceive(const struct MambaPredictor *predictor,
+                                   const char *const *elements,
+                                   const float *coords,
+                                   uintptr_t natoms,
+                                   int32_t charge,
+                                   uint32_t multiplicity);
+
+/*
+ Number of perceived bonds, 0 if `result` is NULL.
+
+ # Safety
+ `result` must be NULL or a valid result of `mamba_perceive`.
+ */
+uintptr_t mamba_result_num_bonds(const struct MambaResult *result);
+
+/*
+ Copies the bonds into `atoms` (`2 * num_bonds` pairs of atom indices) and `orders` (`num_bonds`).
+
+ Orders are 1 (single), 2 (double), 3 (triple) and 4 (aromatic).
+ Returns 0, or -1 if a pointer is NULL.
+
+ # Safety
+ `result` must be NULL or a valid result, the buffers must have the sizes given above.
+ */
+int32_t mamba_result_bonds(const struct MambaResult *result, uint32_t *atoms, uint32_t *orders);
+
+/*
+ Copies the formal charges into `charges` (`natoms` values).
+ Returns 0, or -1 if a pointer is NULL.
+
+ # Safety
+ `result` must be NULL or a valid result, `charges` must hold `natoms` values.
+ */
+int32_t mamba_result_formal_charges(const struct MambaResult *result, int32_t *charges);
+
+/*
+ Copies the number of unpaired electrons per atom into `radicals` (`natoms` values).
+ Returns 0, or -1 if a pointer is NULL.
+
+ # Safety
+ `result` must be NULL or a valid result, `radicals` must hold `natoms` values.
+ */
+int32_t mamba_result_radicals(const struct MambaResult *result, uint32_t *radicals);
+
+/*
+ Frees a result of `mamba_perceive`.
+
+ # Safety
+ `result` must be NULL or returned by `mamba_perceive` and not freed before.
+ */
+void mamba_result_free(struct MambaResult *result);
+
+#endif /* MAMBA_H */
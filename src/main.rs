@@ -8,20 +8,335 @@
 //!
 //! babel -m -h --gend3D -isdf ..\..\opera_data\OPERA_BP\TST_BP_1358.sdf -oxyz test.xyz
 //! Training data needs to be in libsvm format currently
+//!
+//! Besides xyz files, final geometries of Gaussian (.log), ORCA (.out) and xtb
+//! outputs can be read directly, including charge and multiplicity.
+//!
+//! Use `-` to read from stdin and write to stdout, e.g.
+//!
+//! cat mol.xyz | mamba - > mol.sdf
+//!
+//! Compressed files (.gz, .zst) are read transparently, several inputs are
+//! processed in batch, e.g. `mamba conformers/*.xyz.gz --compress gz`. Failing
+//! inputs are reported and skipped, the exit code is non-zero if any failed.
+//!
+//! `mamba serve --port 8080` keeps the model loaded and answers HTTP requests,
+//! see the `server` module for the endpoints.
+//!
+//! Periodic systems take the cell from an extended xyz `Lattice="..."` comment or
+//! from `--lattice`, distances then use the minimum image, the cell has to be at
+//! least twice the distance cut off wide. `--unwrap` makes
+//! molecules crossing the cell boundary whole before writing.
+//!
+//! Coordinates are expected in Angstrom, `--units bohr` converts them before the
+//! featurisation and `--units auto` guesses the units from the bond lengths.
+//! This only applies to xyz inputs, quantum chemistry outputs are read in Angstrom.
+//!
+//! The geometry is checked before prediction (clashes, duplicated atoms, NaN
+//! coordinates, unknown elements, collapsed geometries). Problems are warnings,
+//! `--fail-on clash,element` (or `all`) makes them errors. NaN coordinates are
+//! always an error.
+//!
+//! `--method radii` perceives single bonds from covalent radii without the model,
+//! `--method auto` does so only for molecules with elements unknown to the model
+//! or clashing atoms. No formal charges or radicals are assigned to such bonds.
+//!
+//! `mamba benchmark mols/*.xyz --reference refs` compares the perceived bonds with
+//! reference SD files of the same name and reports the fraction of molecules
+//! which are completely correct, precision and recall per bond type and the
+//! bonds which differ.
+//!
+//! `--train`/`--test` evaluation reports a confusion matrix, precision, recall and
+//! F1 per bond class and Cohen's kappa, `--metrics eval.json` writes them as JSON.
+//! `--breakdown errors.csv` writes the accuracy by element pair and distance bin.
+//!
+//! `mamba train train.libsvm --test test.libsvm --early-stopping 20 --log curve.csv`
+//! stops once the test mlogloss no longer improves, saves the model of the best
+//! round and writes the train/test metrics of every round for plotting.
+//!
+//! `mamba featurize mols/*.xyz --reference refs -o train.libsvm` writes training
+//! data with the molecule of each row as `qid`, `mamba crossval train.libsvm -k 5`
+//! cross-validates with all rows of a molecule in the same fold. The qids count
+//! from `--qid-offset`, so that the outputs of several runs can be concatenated.
+//!
+//! `mamba search train.libsvm --max-depth 4,6,8 --eta 0.05,0.1 --random 10` scores
+//! hyperparameters by grouped cross-validation and saves the best model.
+//!
+//! Trained models get a `<model>.json` with the feature layout, class mapping,
+//! training data hash, parameters, metrics and mamba version. Models whose
+//! feature layout differs from this build are refused when loaded.
+//!
+//! `--cutoff 4.0 --neighbors 5` of `featurize`, `train` and `search` change the
+//! featurisation for experiments. `featurize` stores it in a `<data>.json` next to
+//! the data set, `train` and `search` take it from there (flags given to them have
+//! to agree), store it in the model metadata and the bond perception with that
+//! model uses it again. `--angles` adds neighbor
+//! angles, the pyramidalisation of both atoms and the torsion across the pair,
+//! `--environment` coordination numbers and `--radial` radial distribution
+//! functions of both atoms, `--multiplicity` the spin multiplicity of the
+//! molecule (the charge is always a feature). The shipped `xgb.model` uses the default
+//! featurisation, no model trained with the extended features is shipped yet.
+//!
+//! `mamba trajectory md.xyz --window 3` reports bonds forming, breaking or changing
+//! order along a multi-frame xyz file as `md_events.csv` and `md_species.sdf`.
 
 use std::error::Error;
-use std::{env, fs};
+use std::io::{self, Read, Write};
+use std::env;
+use std::path::Path;
+
+use clap::{command, value_parser, Arg, ArgAction, ArgGroup, ArgMatches, Command};
+use clap::parser::ValueSource;
+use ndarray::Array2;
+use polars::prelude::*;
+use xgboost::Booster;
 
-use clap::{Arg, ArgGroup,command};
+use mambalib::benchmark::{mol_with_reference, predicted_bonds, Benchmark};
+use mambalib::breakdown::breakdown_csv;
+use mambalib::crossval::{cross_validate, load_grouped};
+use mambalib::dataset::{libsvm_rows, reference_labels};
+use mambalib::fileio::{self, Compression};
+use mambalib::metadata::{self, ModelMetadata};
+use mambalib::ml::{
+    check_columns, eval_xgb, load_model_with_config, train_booster, train_xgb, TrainParams,
+};
+use mambalib::pbc::{self, parse_lattice};
+use mambalib::radii::{predict_with_method, Method};
+use mambalib::search::{search, trials_csv, trials_table, SearchSpace};
+use mambalib::server;
+use mambalib::trajectory::{bond_events, changed_species_sdf, events_csv, perceive_frames};
+use mambalib::units::{self, Units};
+use mambalib::valence::postprocess;
+use mambalib::validate::{validate, Check, Validation, ALL_CHECKS};
+use mambalib::{
+    bond_orders, create_dataframe, create_json, create_molblock, mol_from_file, mol_from_string,
+    mols_from_xyz_file, FeatureConfig, Float, XYZMolecule,
+};
+
+/// Reads a molecule from a file or from stdin if the name is `-`
+fn read_input(filename: &str) -> Result<XYZMolecule, Box<dyn Error>> {
+    if filename == "-" {
+        let mut contents = String::new();
+        io::stdin().read_to_string(&mut contents)?;
+        mol_from_string(&contents)
+    } else {
+        mol_from_file(filename)
+    }
+}
+
+/// Writes to a file or to stdout if the name is `-`, compressed files are recognized by extension
+fn write_output(outfile: &str, contents: &[u8]) -> Result<(), Box<dyn Error>> {
+    if outfile == "-" {
+        io::stdout().write_all(contents)?;
+    } else {
+        eprintln!("Writing {}", outfile);
+        fileio::write(outfile, contents)?;
+    }
+    Ok(())
+}
+
+/// Settings for the bond perception of the inputs
+struct Options {
+    format: String,
+    verbose: bool,
+    /// periodic cell replacing the one of the input
+    lattice: Option<Array2<Float>>,
+    /// unwrap periodic molecules before writing
+    unwrap: bool,
+    /// units of the input coordinates, converted to Angstrom before featurisation
+    units: Units,
+    /// geometry checks before prediction
+    validation: Validation,
+    /// model, covalent radii or model with covalent radii fallback
+    method: Method,
+    /// added to the covalent radii sum for the radii method
+    tolerance: Float,
+    /// model with its feature config, not loaded for the radii method
+    model: Option<(Booster, FeatureConfig)>,
+}
+
+/// Bond perception for a single input, returns the output in the requested format
+fn perceive(filename: &str, options: &Options) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut mol = read_input(filename)?;
+    if options.lattice.is_some() {
+        mol.lattice = options.lattice.clone();
+    }
+    // fails on NaN coordinates before anything computes distances from them
+    let issues =
+        validate(&mol, &options.validation).map_err(|e| format!("{}: {}", filename, e))?;
+    for issue in issues {
+        eprintln!("Warning: {}: {}", filename, issue);
+    }
+    if units::to_angstrom(&mut mol, options.units)? && options.verbose {
+        eprintln!("Converted {} from Bohr to Angstrom", filename);
+    }
+    let model = options.model.as_ref().map(|(booster, config)| (booster, config));
+    let (df, used) =
+        predict_with_method(&mol, options.method, options.tolerance, model, filename)?;
+    // covalent radii only give single bonds, the valence rules would misread them
+    let df = match used {
+        Method::Radii => df,
+        _ => postprocess(&mut mol, df)?,
+    };
+    if options.verbose {
+        eprintln!("{}", df);
+    }
+    if options.unwrap {
+        pbc::unwrap(&mut mol, &bond_orders(&df)?)?;
+    }
+    let contents = match options.format.as_str() {
+        "csv" => {
+            let mut buf = Vec::<u8>::new();
+            CsvWriter::new(&mut buf)
+                .has_header(true)
+                .with_delimiter(b',')
+                .finish(&df)?;
+            buf
+        }
+        "json" => (create_json(&mol, &df)? + "\n").into_bytes(),
+        _ => create_molblock(mol, df)?.into_bytes(),
+    };
+    Ok(contents)
+}
+
+/// Values of a comma separated search argument, the default value if not given
+fn search_values<T: Clone + Send + Sync + 'static>(
+    args: &ArgMatches,
+    name: &str,
+    default: &[T],
+) -> Vec<T> {
+    match args.get_many::<T>(name) {
+        Some(values) => values.cloned().collect(),
+        None => default.to_vec(),
+    }
+}
+
+/// Geometry checks given by `--fail-on`, `all` selects every check
+fn fail_on(arguments: &ArgMatches) -> Vec<Check> {
+    let names: Vec<&String> = arguments
+        .get_many::<String>("fail-on")
+        .into_iter()
+        .flatten()
+        .collect();
+    if names.iter().any(|n| *n == "all") {
+        return ALL_CHECKS.to_vec();
+    }
+    names.iter().filter_map(|n| n.parse().ok()).collect()
+}
+
+/// Featurisation of the training data, defaults to that of the shipped model
+fn feature_args() -> [Arg; 6] {
+    [
+        Arg::new("cutoff")
+            .long("cutoff")
+            .value_name("ANGSTROM")
+            .value_parser(value_parser!(Float))
+            .help("pairs further apart are left out of the feature table [default: 3.0]"),
+        Arg::new("neighbors")
+            .long("neighbors")
+            .value_name("N")
+            .value_parser(value_parser!(usize))
+            .help("nearest neighbors of each atom of a pair in the feature table [default: 3]"),
+        Arg::new("angles")
+            .long("angles")
+            .action(ArgAction::SetTrue)
+            .help("adds neighbor angles, pyramidalisation and the torsion across the pair"),
+        Arg::new("environment")
+            .long("environment")
+            .action(ArgAction::SetTrue)
+            .help("adds coordination numbers, heavy atom and hydrogen counts of both atoms"),
+        Arg::new("radial")
+            .long("radial")
+            .action(ArgAction::SetTrue)
+            .help("adds radial distribution functions of both atoms"),
+        Arg::new("multiplicity")
+            .long("multiplicity")
+            .action(ArgAction::SetTrue)
+            .help("adds the spin multiplicity of the molecule"),
+    ]
+}
+
+/// Feature config given by `--cutoff`, `--neighbors` and the extended feature flags
+fn feature_config(arguments: &ArgMatches) -> FeatureConfig {
+    let default = FeatureConfig::default();
+    FeatureConfig {
+        dist_cutoff: arguments
+            .get_one::<Float>("cutoff")
+            .cloned()
+            .unwrap_or(default.dist_cutoff),
+        n_cut: arguments
+            .get_one::<usize>("neighbors")
+            .cloned()
+            .unwrap_or(default.n_cut),
+        angles: arguments.get_flag("angles"),
+        environment: arguments.get_flag("environment"),
+        radial: arguments.get_flag("radial"),
+        multiplicity: arguments.get_flag("multiplicity"),
+    }
+}
+
+/// Feature config of libsvm data sets, from the metadata `featurize` wrote next to them,
+/// feature flags given on the command line have to agree with it
+fn dataset_config(
+    arguments: &ArgMatches,
+    datasets: &[&str],
+) -> Result<FeatureConfig, Box<dyn Error>> {
+    let flags = feature_config(arguments);
+    let explicit = [
+        "cutoff",
+        "neighbors",
+        "angles",
+        "environment",
+        "radial",
+        "multiplicity",
+    ]
+    .iter()
+        .any(|id| arguments.value_source(id) == Some(ValueSource::CommandLine));
+    let mut config: Option<FeatureConfig> = None;
+    for dataset in datasets {
+        match (metadata::dataset_config(dataset)?, config) {
+            (None, _) => eprintln!(
+                "Warning: no metadata for {}, assuming the featurisation of the flags",
+                dataset
+            ),
+            (Some(recorded), Some(previous)) if recorded != previous => {
+                return Err(format!("{} was featurised with {:?}", dataset, recorded).into());
+            }
+            (Some(recorded), _) => config = Some(recorded),
+        }
+    }
+    match config {
+        Some(config) if explicit && config != flags => Err(format!(
+            "The data was featurised with {:?}, the flags give {:?}",
+            config, flags
+        )
+        .into()),
+        Some(config) => Ok(config),
+        None => Ok(flags),
+    }
+}
 
-use mambalib::ml::{eval_xgb, predict_mol};
-use mambalib::{create_molblock, mol_from_xyz_file};
+/// Comma separated values of a hyperparameter for the search
+fn search_arg(name: &'static str) -> Arg {
+    Arg::new(name)
+        .long(name)
+        .value_name("VALUES")
+        .value_delimiter(',')
+        .num_args(1)
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
     let snake = String::from_utf8(vec![0xF0, 0x9F, 0x90, 0x8D]).unwrap();
-    println!("{} mamba-rs {}", snake, snake);
+    eprintln!("{} mamba-rs {}", snake, snake);
 
     let arguments = command!()
+        .arg(
+            Arg::new("input")
+                .value_name("FILE")
+                .num_args(1..)
+                .help("xyz files or quantum chemistry outputs (optionally .gz/.zst), - for stdin")
+                .conflicts_with("filename"),
+        )
         .arg(
             Arg::new("filename")
                 .short('f')
@@ -29,6 +344,80 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .value_name("NAME")
                 
         )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("OUTPUT")
+                .help("output file, - for stdout [default: input name with format extension]"),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_name("FORMAT")
+                .value_parser(["sdf", "csv", "json"])
+                .default_value("sdf")
+                .help("output format, csv writes the feature table with predictions, json atoms and bonds"),
+        )
+        .arg(
+            Arg::new("compress")
+                .long("compress")
+                .value_name("COMPRESSION")
+                .value_parser(["gz", "zst"])
+                .help("compress output files named after the input"),
+        )
+        .arg(
+            Arg::new("lattice")
+                .long("lattice")
+                .value_name("VECTORS")
+                .help("periodic cell as 9 numbers (cell vectors) or 3 (orthorhombic), e.g. \"10 10 12\""),
+        )
+        .arg(
+            Arg::new("units")
+                .long("units")
+                .value_name("UNITS")
+                .value_parser(value_parser!(Units))
+                .default_value("angstrom")
+                .help("units of xyz coordinates and --lattice: angstrom, bohr or auto"),
+        )
+        .arg(
+            Arg::new("method")
+                .long("method")
+                .value_name("METHOD")
+                .value_parser(value_parser!(Method))
+                .default_value("model")
+                .help("model, radii (covalent radii, single bonds only) or auto (radii outside the model's domain)"),
+        )
+        .arg(
+            Arg::new("tolerance")
+                .long("tolerance")
+                .value_name("ANGSTROM")
+                .value_parser(value_parser!(Float))
+                .default_value("0.45")
+                .help("added to the covalent radii sum for --method radii"),
+        )
+        .arg(
+            Arg::new("fail-on")
+                .long("fail-on")
+                .value_name("CHECKS")
+                .value_delimiter(',')
+                .value_parser(["clash", "duplicate", "nonfinite", "element", "collapsed", "all"])
+                .help("geometry checks which are errors instead of warnings, e.g. clash,element"),
+        )
+        .arg(
+            Arg::new("clash-factor")
+                .long("clash-factor")
+                .value_name("FRACTION")
+                .value_parser(value_parser!(Float))
+                .default_value("0.5")
+                .help("atoms closer than this fraction of their covalent radii sum clash"),
+        )
+        .arg(
+            Arg::new("unwrap")
+                .long("unwrap")
+                .action(ArgAction::SetTrue)
+                .help("make molecules crossing the periodic boundary whole before writing"),
+        )
         .arg(
             Arg::new("train-dataset")
                 .long("train")
@@ -41,28 +430,537 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .value_name("TEST_DATASET")
                 .requires("train-dataset"),
         )
+        .arg(
+            Arg::new("metrics")
+                .long("metrics")
+                .value_name("JSON")
+                .requires("test-dataset")
+                .help("writes the evaluation metrics of the test dataset as JSON"),
+        )
+        .arg(
+            Arg::new("breakdown")
+                .long("breakdown")
+                .value_name("CSV")
+                .requires("test-dataset")
+                .help("writes the accuracy by element pair and distance bin as CSV"),
+        )
+        .arg(
+            Arg::new("model")
+                .long("model")
+                .default_value("xgb.model")
+                .help("model for the bond perception and the evaluation of the test dataset"),
+        )
         .group(ArgGroup::new("datasets").args(&["train-dataset", "test-dataset"]))
-        .arg(Arg::new("verbose").short('v').long("verbose"))
+        .arg(Arg::new("verbose").short('v').long("verbose").action(ArgAction::SetTrue))
+        .subcommand(
+            Command::new("serve")
+                .about("Serves bond perception via HTTP, keeping the model loaded")
+                .arg(
+                    Arg::new("port")
+                        .short('p')
+                        .long("port")
+                        .value_parser(value_parser!(u16))
+                        .default_value("8080"),
+                )
+                .arg(Arg::new("host").long("host").default_value("127.0.0.1"))
+                .arg(Arg::new("model").long("model").default_value("xgb.model")),
+        )
+        .subcommand(
+            Command::new("benchmark")
+                .about("Compares the perceived bonds of xyz files with reference SD files")
+                .arg(
+                    Arg::new("input")
+                        .value_name("FILE")
+                        .num_args(1..)
+                        .required(true)
+                        .help("xyz files, the reference of mol.xyz is mol.sdf"),
+                )
+                .arg(
+                    Arg::new("reference")
+                        .short('r')
+                        .long("reference")
+                        .value_name("DIR")
+                        .help("directory of the reference SD files [default: next to the xyz files]"),
+                )
+                .arg(
+                    Arg::new("method")
+                        .long("method")
+                        .value_name("METHOD")
+                        .value_parser(value_parser!(Method))
+                        .default_value("model"),
+                )
+                .arg(
+                    Arg::new("tolerance")
+                        .long("tolerance")
+                        .value_name("ANGSTROM")
+                        .value_parser(value_parser!(Float))
+                        .default_value("0.45"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("OUTPUT")
+                        .default_value("-")
+                        .help("file for the report, - for stdout"),
+                )
+                .arg(Arg::new("model").long("model").default_value("xgb.model")),
+        )
+        .subcommand(
+            Command::new("featurize")
+                .about("Writes the feature table of xyz files with the bonds of reference SD files as libsvm training data")
+                .args(feature_args())
+                .arg(
+                    Arg::new("input")
+                        .value_name("FILE")
+                        .num_args(1..)
+                        .required(true)
+                        .help("xyz files, the reference of mol.xyz is mol.sdf"),
+                )
+                .arg(
+                    Arg::new("reference")
+                        .short('r')
+                        .long("reference")
+                        .value_name("DIR")
+                        .help("directory of the reference SD files [default: next to the xyz files]"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("OUTPUT")
+                        .default_value("-")
+                        .help("libsvm file, the molecule of each row is its qid"),
+                )
+                .arg(
+                    Arg::new("qid-offset")
+                        .long("qid-offset")
+                        .value_name("N")
+                        .value_parser(value_parser!(usize))
+                        .default_value("0")
+                        .help("first qid, to concatenate the output of several runs"),
+                ),
+        )
+        .subcommand(
+            Command::new("train")
+                .about("Trains the model on a libsvm file, stopping early on the test set")
+                .args(feature_args())
+                .arg(Arg::new("input").value_name("LIBSVM").required(true))
+                .arg(
+                    Arg::new("test")
+                        .short('t')
+                        .long("test")
+                        .value_name("LIBSVM")
+                        .required(true)
+                        .help("evaluation set for the training curve and early stopping"),
+                )
+                .arg(
+                    Arg::new("rounds")
+                        .long("rounds")
+                        .value_parser(value_parser!(u32))
+                        .default_value("200")
+                        .help("maximum number of boosting rounds"),
+                )
+                .arg(
+                    Arg::new("early-stopping")
+                        .long("early-stopping")
+                        .value_name("ROUNDS")
+                        .value_parser(value_parser!(u32))
+                        .help("stops when the test mlogloss has not improved for ROUNDS rounds"),
+                )
+                .arg(
+                    Arg::new("log")
+                        .long("log")
+                        .value_name("CSV")
+                        .help("writes the train and test metrics of every round"),
+                )
+                .arg(
+                    Arg::new("model-out")
+                        .long("model-out")
+                        .value_name("MODEL")
+                        .default_value("xgb.model")
+                        .help("model of the best round"),
+                ),
+        )
+        .subcommand(
+            Command::new("crossval")
+                .about("Cross-validates the model with the molecules (qid) of a libsvm file kept in one fold")
+                .arg(Arg::new("input").value_name("LIBSVM").required(true))
+                .arg(
+                    Arg::new("folds")
+                        .short('k')
+                        .long("folds")
+                        .value_parser(value_parser!(usize))
+                        .default_value("5"),
+                )
+                .arg(
+                    Arg::new("seed")
+                        .long("seed")
+                        .value_parser(value_parser!(u64))
+                        .default_value("42"),
+                )
+                .arg(
+                    Arg::new("oof")
+                        .long("oof")
+                        .value_name("CSV")
+                        .help("writes the out-of-fold predictions"),
+                ),
+        )
+        .subcommand(
+            Command::new("search")
+                .about("Searches hyperparameters by molecule-grouped cross-validation, values are comma separated")
+                .args(feature_args())
+                .arg(Arg::new("input").value_name("LIBSVM").required(true))
+                .arg(search_arg("max-depth").value_parser(value_parser!(u32)))
+                .arg(search_arg("eta").value_parser(value_parser!(f32)))
+                .arg(search_arg("subsample").value_parser(value_parser!(f32)))
+                .arg(search_arg("colsample").value_parser(value_parser!(f32)))
+                .arg(search_arg("min-child-weight").value_parser(value_parser!(f32)))
+                .arg(search_arg("rounds").value_parser(value_parser!(u32)))
+                .arg(
+                    Arg::new("random")
+                        .long("random")
+                        .value_name("N")
+                        .value_parser(value_parser!(usize))
+                        .help("random search of N combinations instead of the full grid"),
+                )
+                .arg(
+                    Arg::new("folds")
+                        .short('k')
+                        .long("folds")
+                        .value_parser(value_parser!(usize))
+                        .default_value("5"),
+                )
+                .arg(
+                    Arg::new("seed")
+                        .long("seed")
+                        .value_parser(value_parser!(u64))
+                        .default_value("42"),
+                )
+                .arg(
+                    Arg::new("metric")
+                        .long("metric")
+                        .value_parser(["accuracy", "kappa", "macro_precision", "macro_recall", "macro_f1"])
+                        .default_value("macro_f1"),
+                )
+                .arg(
+                    Arg::new("results")
+                        .long("results")
+                        .value_name("CSV")
+                        .help("writes all trials, best first"),
+                )
+                .arg(
+                    Arg::new("model-out")
+                        .long("model-out")
+                        .value_name("MODEL")
+                        .default_value("best.model")
+                        .help("model trained on all data with the best parameters"),
+                ),
+        )
+        .subcommand(
+            Command::new("trajectory")
+                .about("Finds bonds forming, breaking or changing order along a multi-frame xyz file")
+                .arg(Arg::new("input").value_name("FILE").required(true))
+                .arg(
+                    Arg::new("window")
+                        .short('w')
+                        .long("window")
+                        .value_parser(value_parser!(usize))
+                        .default_value("1")
+                        .help("frames a new bond order has to persist, suppresses vibrations"),
+                )
+                .arg(
+                    Arg::new("units")
+                        .long("units")
+                        .value_name("UNITS")
+                        .value_parser(value_parser!(Units))
+                        .default_value("angstrom")
+                        .help("units of the input coordinates: angstrom, bohr or auto"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("PREFIX")
+                        .help("writes PREFIX_events.csv and PREFIX_species.sdf [default: input name]"),
+                )
+                .arg(Arg::new("model").long("model").default_value("xgb.model")),
+        )
         .get_matches();
 
+    if let Some(("serve", serve_args)) = arguments.subcommand() {
+        let addr = format!(
+            "{}:{}",
+            serve_args.get_one::<String>("host").unwrap(),
+            serve_args.get_one::<u16>("port").unwrap()
+        );
+        let (booster, config) =
+            load_model_with_config(serve_args.get_one::<String>("model").unwrap())?;
+        return server::serve(&addr, &booster, &config);
+    }
+
+    if let Some(("benchmark", bench_args)) = arguments.subcommand() {
+        let method = *bench_args.get_one::<Method>("method").unwrap();
+        let tolerance = *bench_args.get_one::<Float>("tolerance").unwrap();
+        let model = match method {
+            Method::Radii => None,
+            _ => Some(load_model_with_config(bench_args.get_one::<String>("model").unwrap())?),
+        };
+        let model = model.as_ref().map(|(booster, config)| (booster, config));
+        let reference_dir = bench_args.get_one::<String>("reference").map(|d| d.as_str());
+        let mut benchmark = Benchmark::default();
+        for input in bench_args.get_many::<String>("input").unwrap() {
+            let (mut mol, ref_bonds) = mol_with_reference(input, reference_dir)?;
+            let (df, used) = predict_with_method(&mol, method, tolerance, model, input)?;
+            let df = match used {
+                Method::Radii => df,
+                _ => postprocess(&mut mol, df)?,
+            };
+            benchmark.add(input, &ref_bonds, &predicted_bonds(&df)?);
+        }
+        let output = bench_args.get_one::<String>("output").unwrap();
+        write_output(output, benchmark.report().as_bytes())?;
+        return Ok(());
+    }
+
+    if let Some(("featurize", feat_args)) = arguments.subcommand() {
+        let reference_dir = feat_args.get_one::<String>("reference").map(|d| d.as_str());
+        let config = feature_config(feat_args);
+        let mut contents = String::new();
+        let qid_offset = *feat_args.get_one::<usize>("qid-offset").unwrap();
+        for (i, input) in feat_args.get_many::<String>("input").unwrap().enumerate() {
+            let (mol, ref_bonds) = mol_with_reference(input, reference_dir)?;
+            let df = create_dataframe(&mol, &config)?;
+            let labels = reference_labels(&df, &ref_bonds)?;
+            contents += libsvm_rows(&df, &labels, qid_offset + i)?.as_str();
+        }
+        let output = feat_args.get_one::<String>("output").unwrap();
+        write_output(output, contents.as_bytes())?;
+        if output != "-" {
+            ModelMetadata::new(config).save(output)?;
+        }
+        return Ok(());
+    }
+
+    if let Some(("train", train_args)) = arguments.subcommand() {
+        let params = TrainParams {
+            rounds: *train_args.get_one::<u32>("rounds").unwrap(),
+            ..Default::default()
+        };
+        let input = train_args.get_one::<String>("input").unwrap();
+        let test = train_args.get_one::<String>("test").unwrap();
+        let log = train_xgb(
+            input,
+            test,
+            &dataset_config(train_args, &[input, test])?,
+            &params,
+            train_args.get_one::<u32>("early-stopping").cloned(),
+            train_args.get_one::<String>("model-out").unwrap(),
+        )?;
+        if let Some(csv) = train_args.get_one::<String>("log") {
+            write_output(csv, log.csv().as_bytes())?;
+        }
+        return Ok(());
+    }
+
+    if let Some(("crossval", cv_args)) = arguments.subcommand() {
+        let cv = cross_validate(
+            cv_args.get_one::<String>("input").unwrap(),
+            *cv_args.get_one::<usize>("folds").unwrap(),
+            &TrainParams::default(),
+            *cv_args.get_one::<u64>("seed").unwrap(),
+        )?;
+        print!("{}", cv.table());
+        if let Some(oof) = cv_args.get_one::<String>("oof") {
+            write_output(oof, cv.oof_csv().as_bytes())?;
+        }
+        return Ok(());
+    }
+
+    if let Some(("search", search_args)) = arguments.subcommand() {
+        let default = SearchSpace::default();
+        let space = SearchSpace {
+            max_depth: search_values(search_args, "max-depth", &default.max_depth),
+            eta: search_values(search_args, "eta", &default.eta),
+            subsample: search_values(search_args, "subsample", &default.subsample),
+            colsample_bytree: search_values(search_args, "colsample", &default.colsample_bytree),
+            min_child_weight: search_values(
+                search_args,
+                "min-child-weight",
+                &default.min_child_weight,
+            ),
+            rounds: search_values(search_args, "rounds", &default.rounds),
+        };
+        let seed = *search_args.get_one::<u64>("seed").unwrap();
+        let candidates = match search_args.get_one::<usize>("random") {
+            Some(n) => space.sample(*n, seed),
+            None => space.grid(),
+        };
+        let input = search_args.get_one::<String>("input").unwrap();
+        let metric = search_args.get_one::<String>("metric").unwrap();
+        let config = dataset_config(search_args, &[input])?;
+        let (dall, qids) = load_grouped(input)?;
+        check_columns(&dall, input, &config)?;
+        let trials = search(
+            &dall,
+            &qids,
+            &candidates,
+            *search_args.get_one::<usize>("folds").unwrap(),
+            seed,
+            metric,
+        )?;
+        print!("{}", trials_table(&trials));
+        if let Some(results) = search_args.get_one::<String>("results") {
+            write_output(results, trials_csv(&trials).as_bytes())?;
+        }
+        let best = &trials.first().ok_or("No search candidates")?.params;
+        let model = search_args.get_one::<String>("model-out").unwrap();
+        eprintln!("Training {} with {:?}", model, best);
+        train_booster(&dall, best, None)?.save(model)?;
+        let mut metadata = ModelMetadata::new(config).with_training_data(input)?;
+        metadata.params = Some(best.clone());
+        if let Some(score) = trials[0].score {
+            metadata.metrics.insert(format!("cv_{}", metric), score);
+        }
+        metadata.save(model)?;
+        return Ok(());
+    }
 
-    if let Some(filename) = arguments.get_one::<String>("filename") { 
-        let mol = mol_from_xyz_file(filename).expect("Could not open file!");
-        let df = predict_mol(&mol);
-        println!("{}", df);
-        let molblock = create_molblock(mol, df)?;
-        let outfile = filename.replace(".xyz", ".sdf");
-        println!("Writing SD file:{}", outfile);
-        fs::write(outfile, molblock).expect("Unable to write SD file");
+    if let Some(("trajectory", traj_args)) = arguments.subcommand() {
+        let input = traj_args.get_one::<String>("input").unwrap();
+        let window = *traj_args.get_one::<usize>("window").unwrap();
+        let prefix = match traj_args.get_one::<String>("output") {
+            Some(prefix) => prefix.clone(),
+            None => fileio::strip_compression(input)
+                .with_extension("")
+                .to_string_lossy()
+                .into_owned(),
+        };
+        let (booster, config) =
+            load_model_with_config(traj_args.get_one::<String>("model").unwrap())?;
+        let units = *traj_args.get_one::<Units>("units").unwrap();
+        let mut mols = mols_from_xyz_file(input)?;
+        // units are decided on the first frame, so a trajectory is never partly converted
+        if let Some((first, rest)) = mols.split_first_mut() {
+            if units::to_angstrom(first, units)? {
+                rest.iter_mut().for_each(units::bohr_to_angstrom);
+            }
+        }
+        let frames = perceive_frames(&booster, &config, mols)?;
+        let events = bond_events(&frames, window);
+        eprintln!("{} bond events in {} frames", events.len(), frames.len());
+        write_output(&(prefix.clone() + "_events.csv"), events_csv(&events).as_bytes())?;
+        let sdf = changed_species_sdf(&frames, &events)?;
+        write_output(&(prefix + "_species.sdf"), sdf.as_bytes())?;
+        return Ok(());
+    }
+
+    let inputs: Vec<&String> = match arguments.get_many::<String>("input") {
+        Some(inputs) => inputs.collect(),
+        None => arguments.get_many::<String>("filename").into_iter().flatten().collect(),
+    };
+    if !inputs.is_empty() {
+        let format = arguments.get_one::<String>("format").unwrap();
+        let method = *arguments.get_one::<Method>("method").unwrap();
+        let options = Options {
+            format: format.clone(),
+            verbose: arguments.get_flag("verbose"),
+            lattice: match arguments.get_one::<String>("lattice") {
+                Some(lattice) => Some(parse_lattice(lattice)?),
+                None => None,
+            },
+            unwrap: arguments.get_flag("unwrap"),
+            units: *arguments.get_one::<Units>("units").unwrap(),
+            validation: Validation {
+                clash_factor: *arguments.get_one::<Float>("clash-factor").unwrap(),
+                fail_on: fail_on(&arguments),
+            },
+            method,
+            tolerance: *arguments.get_one::<Float>("tolerance").unwrap(),
+            model: match method {
+                Method::Radii => None,
+                _ => Some(load_model_with_config(arguments.get_one::<String>("model").unwrap())?),
+            },
+        };
+        let compression = match arguments.get_one::<String>("compress").map(|c| c.as_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        };
+        let output = arguments.get_one::<String>("output").cloned().or_else(|| {
+            if inputs.iter().any(|f| *f == "-") {
+                Some("-".to_owned())
+            } else {
+                None
+            }
+        });
+        // a failing input is reported and the others are still processed
+        let mut failed = 0;
+        match output {
+            // everything into one file, several molecules as SD records
+            Some(outfile) => {
+                let mut contents = Vec::<u8>::new();
+                let mut header_written = false;
+                for filename in inputs.iter() {
+                    let mut perceived = match perceive(filename, &options) {
+                        Ok(perceived) => perceived,
+                        Err(e) => {
+                            eprintln!("Error: {}: {}", filename, e);
+                            failed += 1;
+                            continue;
+                        }
+                    };
+                    // one CSV header for all inputs
+                    if format == "csv" && header_written {
+                        let header = perceived.iter().position(|b| *b == b'\n');
+                        perceived.drain(..header.map_or(0, |n| n + 1));
+                    }
+                    header_written = true;
+                    contents.append(&mut perceived);
+                    if inputs.len() > 1 && format == "sdf" {
+                        contents.extend_from_slice(b"$$$$\n");
+                    }
+                }
+                write_output(&outfile, &contents)?;
+            }
+            // batch mode, one output per input
+            None => {
+                for filename in inputs.iter() {
+                    let outfile = fileio::strip_compression(filename).with_extension(format);
+                    let outfile = outfile.to_string_lossy().into_owned() + compression.suffix();
+                    let result = if Path::new(&outfile) == Path::new(filename.as_str()) {
+                        Err(format!("output {} would overwrite the input, use -o", outfile).into())
+                    } else {
+                        perceive(filename, &options)
+                            .and_then(|contents| write_output(&outfile, &contents))
+                    };
+                    if let Err(e) = result {
+                        eprintln!("Error: {}: {}", filename, e);
+                        failed += 1;
+                    }
+                }
+            }
+        }
+        if failed > 0 {
+            return Err(format!("{} of {} inputs failed", failed, inputs.len()).into());
+        }
     } else {
         // If you want to access the train and test datasets:
         if let Some(train_dataset) = arguments.get_one::<String>("train-dataset") {
             println!("Train dataset: {}", train_dataset);
             //train_xgb("../mamba/libsvm_large.dat","../mamba/3dqsar_test.dat");
             // Test dataset is optional, so use if let
-            if let Some(test_dataset) = arguments.get_one::<String>("train-dataset") {
+            if let Some(test_dataset) = arguments.get_one::<String>("test-dataset") {
                 println!("Test dataset: {}", test_dataset);
-                eval_xgb(test_dataset);
+                let model = arguments.get_one::<String>("model").unwrap();
+                let evaluation = eval_xgb(test_dataset, model)?;
+                if let Some(metrics) = arguments.get_one::<String>("metrics") {
+                    let json = serde_json::to_string_pretty(&evaluation.report)? + "\n";
+                    write_output(metrics, json.as_bytes())?;
+                }
+                if let Some(csv) = arguments.get_one::<String>("breakdown") {
+                    write_output(csv, breakdown_csv(&evaluation.breakdown).as_bytes())?;
+                }
                 //eval_xgb("../mamba/libsvm_large.dat");
             }
         }    
@@ -0,0 +1,276 @@
+//! Benchmark of the perceived bonds against reference SD files.
+//!
+//! Every xyz file is paired with a reference molfile with the same atom order.
+//! The bonds of the whole pipeline (prediction and valence post-processing) are
+//! compared with the reference bonds: a molecule is only correct if all its bonds
+//! and bond orders match. Per bond class precision and recall come from a
+//! confusion matrix over all atom pairs which are bonded in either of them.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use polars::prelude::DataFrame;
+
+use crate::elements::parse_element;
+use crate::metrics::{format_metric, ratio, ConfusionMatrix, BOND_CLASSES};
+use crate::{bond_orders, fileio, mol_from_file, Float, XYZMolecule};
+
+/// Bonds by atom pair (zero based, `i < j`), order 4 is aromatic
+pub type BondMap = BTreeMap<(usize, usize), u32>;
+
+/// Atoms and bonds of the first record of a V2000 molfile or SD file
+pub fn molblock_bonds(contents: &str) -> Result<(Vec<String>, BondMap), Box<dyn Error>> {
+    let lines: Vec<&str> = contents.lines().collect();
+    let counts = lines.get(3).ok_or("Molfile without counts line")?;
+    if counts.len() < 6 {
+        return Err(format!("Malformed counts line: {}", counts).into());
+    }
+    // fixed width, but also the space separated counts of our own molblocks
+    let (natoms, nbonds): (usize, usize) =
+        match (counts[0..3].trim().parse(), counts[3..6].trim().parse()) {
+            (Ok(natoms), Ok(nbonds)) => (natoms, nbonds),
+            _ => {
+                let mut tokens = counts.split_whitespace();
+                (
+                    tokens.next().unwrap_or_default().parse()?,
+                    tokens.next().unwrap_or_default().parse()?,
+                )
+            }
+        };
+    if lines.len() < 4 + natoms + nbonds {
+        return Err("Molfile is shorter than given by its counts line".into());
+    }
+    let atoms = lines[4..4 + natoms]
+        .iter()
+        .map(|line| {
+            line.split_whitespace()
+                .nth(3)
+                .unwrap_or_default()
+                .to_owned()
+        })
+        .collect();
+    let mut bonds = BondMap::new();
+    for line in &lines[4 + natoms..4 + natoms + nbonds] {
+        if line.len() < 9 {
+            return Err(format!("Malformed bond line: {}", line).into());
+        }
+        let a: usize = line[0..3].trim().parse()?;
+        let b: usize = line[3..6].trim().parse()?;
+        let order: u32 = line[6..9].trim().parse()?;
+        if a == 0 || b == 0 || a > natoms || b > natoms {
+            return Err(format!("Invalid atom index in bond line: {}", line).into());
+        }
+        bonds.insert(((a - 1).min(b - 1), (a - 1).max(b - 1)), order);
+    }
+    Ok((atoms, bonds))
+}
+
+/// Predicted bonds of a dataframe with `id1`, `id2` and `preds`
+pub fn predicted_bonds(df: &DataFrame) -> Result<BondMap, Box<dyn Error>> {
+    Ok(bond_orders(df)?
+        .into_iter()
+        .filter(|(_, _, order)| *order > 0)
+        .map(|(a, b, order)| ((a.min(b), a.max(b)), order))
+        .collect())
+}
+
+/// Reference SD file of an xyz file, in `dir` or next to the xyz file
+pub fn reference_path(xyz: &str, dir: Option<&str>) -> PathBuf {
+    let stripped = fileio::strip_compression(xyz);
+    let name = stripped.with_extension("sdf");
+    match dir {
+        Some(dir) => Path::new(dir).join(name.file_name().unwrap_or_default()),
+        None => name,
+    }
+}
+
+/// Molecule of an xyz file with the bonds of its reference SD file, the atoms have to match
+pub fn mol_with_reference(
+    xyz: &str,
+    dir: Option<&str>,
+) -> Result<(XYZMolecule, BondMap), Box<dyn Error>> {
+    let reference = reference_path(xyz, dir);
+    let (ref_atoms, ref_bonds) =
+        molblock_bonds(&fileio::read_to_string(&reference.to_string_lossy())?)
+            .map_err(|e| format!("{}: {}", reference.display(), e))?;
+    let mol = mol_from_file(xyz)?;
+    let same_atoms = ref_atoms.len() == mol.natoms
+        && ref_atoms
+            .iter()
+            .zip(mol.elements.iter())
+            .all(|(a, e)| parse_element(a).ok() == *e);
+    if !same_atoms {
+        return Err(format!("{}: atoms differ from {}", xyz, reference.display()).into());
+    }
+    Ok((mol, ref_bonds))
+}
+
+/// Bond of a molecule which differs from the reference, order 0 is no bond
+#[derive(Debug, Clone, PartialEq)]
+pub struct BondDifference {
+    pub atom1: usize,
+    pub atom2: usize,
+    pub reference: u32,
+    pub predicted: u32,
+}
+
+/// Result of a single molecule
+#[derive(Debug, Clone)]
+pub struct MoleculeResult {
+    pub name: String,
+    pub nbonds: usize,
+    pub differences: Vec<BondDifference>,
+}
+
+/// Collected results of all molecules
+pub struct Benchmark {
+    pub molecules: Vec<MoleculeResult>,
+    pub confusion: ConfusionMatrix,
+}
+
+impl Default for Benchmark {
+    fn default() -> Self {
+        Benchmark {
+            molecules: Vec::new(),
+            confusion: ConfusionMatrix::new(BOND_CLASSES.len()),
+        }
+    }
+}
+
+impl Benchmark {
+    /// Compares the predicted bonds of a molecule with its reference
+    pub fn add(&mut self, name: &str, reference: &BondMap, predicted: &BondMap) {
+        let pairs: BTreeSet<&(usize, usize)> = reference.keys().chain(predicted.keys()).collect();
+        let mut differences = Vec::<BondDifference>::new();
+        for pair in pairs {
+            let r = reference.get(pair).cloned().unwrap_or(0);
+            let p = predicted.get(pair).cloned().unwrap_or(0);
+            self.confusion.add(r as usize, p as usize);
+            if r != p {
+                differences.push(BondDifference {
+                    atom1: pair.0,
+                    atom2: pair.1,
+                    reference: r,
+                    predicted: p,
+                });
+            }
+        }
+        self.molecules.push(MoleculeResult {
+            name: name.to_owned(),
+            nbonds: reference.len(),
+            differences,
+        });
+    }
+
+    /// Fraction of molecules with all bonds correct
+    pub fn exact_match_rate(&self) -> Option<Float> {
+        let correct = self
+            .molecules
+            .iter()
+            .filter(|m| m.differences.is_empty())
+            .count();
+        ratio(correct, self.molecules.len())
+    }
+
+    /// Text report with the summary, the metrics per bond class and the wrong molecules
+    pub fn report(&self) -> String {
+        let ncorrect = self
+            .molecules
+            .iter()
+            .filter(|m| m.differences.is_empty())
+            .count();
+        let mut report = format!(
+            "molecules: {}, exact matches: {} ({})\n\n",
+            self.molecules.len(),
+            ncorrect,
+            format_metric(self.exact_match_rate())
+        );
+        report += format!("{:>10}{:>10}{:>10}\n", "bond", "precision", "recall").as_str();
+        for (class, label) in BOND_CLASSES.iter().enumerate().skip(1) {
+            report += format!(
+                "{:>10}{:>10}{:>10}\n",
+                label,
+                format_metric(self.confusion.precision(class)),
+                format_metric(self.confusion.recall(class))
+            )
+            .as_str();
+        }
+        report += "\n";
+        report += self.confusion.table(BOND_CLASSES).as_str();
+        let wrong: Vec<&MoleculeResult> = self
+            .molecules
+            .iter()
+            .filter(|m| !m.differences.is_empty())
+            .collect();
+        if !wrong.is_empty() {
+            report += "\nmolecules with errors (atom indices one based, reference -> predicted):\n";
+        }
+        for mol in wrong {
+            let diffs: Vec<String> = mol
+                .differences
+                .iter()
+                .map(|d| {
+                    format!(
+                        "{}-{}: {} -> {}",
+                        d.atom1 + 1,
+                        d.atom2 + 1,
+                        d.reference,
+                        d.predicted
+                    )
+                })
+                .collect();
+            report += format!("{}: {}\n", mol.name, diffs.join(", ")).as_str();
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMALDEHYDE: &str = "formaldehyde
+ML generated sdf
+
+4 3  0  0  0  0  0  0  0  0  1 V2000
+    0.0000    0.0000    0.0000 C  0  0  0  0  0
+    0.0000    0.0000    1.2000 O  0  0  0  0  0
+    0.9400    0.0000   -0.5400 H  0  0  0  0  0
+   -0.9400    0.0000   -0.5400 H  0  0  0  0  0
+  1  2  2 0  0  0  0  0
+  1  3  1 0  0  0  0  0
+  4  1  1 0  0  0  0  0
+M  END
+";
+
+    #[test]
+    fn test_molblock_bonds() {
+        let (atoms, bonds) = molblock_bonds(FORMALDEHYDE).unwrap();
+        assert_eq!(atoms, vec!["C", "O", "H", "H"]);
+        assert_eq!(bonds.get(&(0, 1)), Some(&2));
+        assert_eq!(bonds.get(&(0, 3)), Some(&1));
+        assert!(molblock_bonds("x\n\n\n").is_err());
+    }
+    #[test]
+    fn test_benchmark() {
+        let (_, reference) = molblock_bonds(FORMALDEHYDE).unwrap();
+        let mut benchmark = Benchmark::default();
+        benchmark.add("correct", &reference, &reference.clone());
+        let mut predicted = reference.clone();
+        predicted.insert((0, 1), 1);
+        predicted.insert((2, 3), 1);
+        benchmark.add("wrong", &reference, &predicted);
+        assert_eq!(benchmark.exact_match_rate(), Some(0.5));
+        assert_eq!(benchmark.molecules[1].differences.len(), 2);
+        assert_eq!(benchmark.confusion.recall(2), Some(0.5));
+        assert_eq!(benchmark.confusion.precision(1), Some(4.0 / 6.0));
+        assert!(benchmark
+            .report()
+            .contains("wrong: 1-2: 2 -> 1, 3-4: 0 -> 1\n"));
+        assert_eq!(
+            reference_path("mols/a.xyz.gz", Some("ref")),
+            PathBuf::from("ref/a.sdf")
+        );
+    }
+}
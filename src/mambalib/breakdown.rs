@@ -0,0 +1,221 @@
+//! Breakdown of the prediction errors by element pair and by distance.
+//!
+//! The feature table holds the elements of a pair in `ata`/`atb` (index in the
+//! element table, heavier element first) and their distance in `distab`, so the
+//! rows of an evaluation data set can be grouped to see in which chemistries the
+//! errors are concentrated, e.g. S-O or P-O bonds.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use serde::Serialize;
+
+use crate::metrics::{format_metric, ratio};
+use crate::{Float, ELEMENTS};
+
+/// Column of `ata` in the feature table, zero based as the libsvm feature indices
+pub const ATA_COLUMN: usize = 3;
+/// Column of `atb` in the feature table
+pub const ATB_COLUMN: usize = 4;
+/// Column of `distab` in the feature table
+pub const DISTAB_COLUMN: usize = 5;
+/// Width of the distance bins in Angstrom
+pub const DISTANCE_BIN: Float = 0.25;
+
+/// Accuracy of a group of rows, e.g. all C-O pairs
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GroupAccuracy {
+    /// `pair` or `distance`
+    pub kind: String,
+    pub group: String,
+    pub samples: usize,
+    /// rows which are bonded in the labels
+    pub bonds: usize,
+    pub errors: usize,
+    pub accuracy: Option<Float>,
+}
+
+/// Selected columns of every row of a libsvm file, missing (sparse) values are zero
+pub fn libsvm_columns(
+    contents: &str,
+    columns: &[usize],
+) -> Result<Vec<Vec<Float>>, Box<dyn Error>> {
+    let mut rows = Vec::<Vec<Float>>::new();
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap_or_default();
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut row = vec![0.0; columns.len()];
+        // the first token is the label
+        for token in line.split_whitespace().skip(1) {
+            let (index, value) = match token.split_once(':') {
+                Some(("qid", _)) | None => continue,
+                Some((index, value)) => (index.parse::<usize>()?, value),
+            };
+            if let Some(k) = columns.iter().position(|c| *c == index) {
+                row[k] = value.parse()?;
+            }
+        }
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Element pair of the `ata`/`atb` values, e.g. `S-O`
+pub fn pair_label(ata: Float, atb: Float) -> String {
+    let symbol = |an: Float| {
+        ELEMENTS
+            .get(an as usize)
+            .map_or("?".to_owned(), |s| s.to_string())
+    };
+    format!("{}-{}", symbol(ata), symbol(atb))
+}
+
+/// Distance bin of a distance, e.g. `1.25-1.50`, distances beyond the cut off of the
+/// data set share one bin
+pub fn distance_label(dist: Float, dist_cutoff: Float) -> String {
+    let nbins = (dist_cutoff / DISTANCE_BIN).ceil() as usize;
+    let bin = ((dist / DISTANCE_BIN).floor().max(0.0) as usize).min(nbins);
+    if bin == nbins {
+        return format!(">{:.2}", dist_cutoff);
+    }
+    format!(
+        "{:.2}-{:.2}",
+        bin as Float * DISTANCE_BIN,
+        (bin + 1) as Float * DISTANCE_BIN
+    )
+}
+
+/// Accuracy of the predictions grouped by the given labels, sorted by the number of errors
+pub fn group_accuracy(
+    kind: &str,
+    groups: &[String],
+    labels: &[f32],
+    preds: &[f32],
+) -> Vec<GroupAccuracy> {
+    let mut counts = BTreeMap::<&String, (usize, usize, usize)>::new();
+    for ((group, label), pred) in groups.iter().zip(labels).zip(preds) {
+        let entry = counts.entry(group).or_insert((0, 0, 0));
+        entry.0 += 1;
+        if *label as i32 > 0 {
+            entry.1 += 1;
+        }
+        if *label as i32 != *pred as i32 {
+            entry.2 += 1;
+        }
+    }
+    let mut result: Vec<GroupAccuracy> = counts
+        .into_iter()
+        .map(|(group, (samples, bonds, errors))| GroupAccuracy {
+            kind: kind.to_owned(),
+            group: group.clone(),
+            samples,
+            bonds,
+            errors,
+            accuracy: ratio(samples - errors, samples),
+        })
+        .collect();
+    result.sort_by(|a, b| b.errors.cmp(&a.errors));
+    result
+}
+
+/// Breakdown of a libsvm evaluation data set by element pair and by distance bin,
+/// `dist_cutoff` is the distance cut off the data set was featurised with
+pub fn breakdown(
+    contents: &str,
+    labels: &[f32],
+    preds: &[f32],
+    dist_cutoff: Float,
+) -> Result<Vec<GroupAccuracy>, Box<dyn Error>> {
+    let rows = libsvm_columns(contents, &[ATA_COLUMN, ATB_COLUMN, DISTAB_COLUMN])?;
+    if rows.len() != preds.len() {
+        return Err(format!("{} rows but {} predictions", rows.len(), preds.len()).into());
+    }
+    let pairs: Vec<String> = rows.iter().map(|r| pair_label(r[0], r[1])).collect();
+    let distances: Vec<String> = rows
+        .iter()
+        .map(|r| distance_label(r[2], dist_cutoff))
+        .collect();
+    let mut result = group_accuracy("pair", &pairs, labels, preds);
+    result.append(&mut group_accuracy("distance", &distances, labels, preds));
+    Ok(result)
+}
+
+/// Breakdown as text table
+pub fn breakdown_table(groups: &[GroupAccuracy]) -> String {
+    let mut table = format!(
+        "{:>10}{:>12}{:>10}{:>10}{:>10}{:>10}\n",
+        "kind", "group", "samples", "bonds", "errors", "accuracy"
+    );
+    for g in groups {
+        table += format!(
+            "{:>10}{:>12}{:>10}{:>10}{:>10}{:>10}\n",
+            g.kind,
+            g.group,
+            g.samples,
+            g.bonds,
+            g.errors,
+            format_metric(g.accuracy)
+        )
+        .as_str();
+    }
+    table
+}
+
+/// Breakdown as CSV
+pub fn breakdown_csv(groups: &[GroupAccuracy]) -> String {
+    let mut csv = String::from("kind,group,samples,bonds,errors,accuracy\n");
+    for g in groups {
+        csv += format!(
+            "{},{},{},{},{},{}\n",
+            g.kind,
+            g.group,
+            g.samples,
+            g.bonds,
+            g.errors,
+            g.accuracy.map_or(String::new(), |a| a.to_string())
+        )
+        .as_str();
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels() {
+        assert_eq!(pair_label(15.0, 7.0), "S-O");
+        assert_eq!(distance_label(1.3, 3.0), "1.25-1.50");
+        assert_eq!(distance_label(0.1, 3.0), "0.00-0.25");
+        assert_eq!(distance_label(3.2, 3.0), ">3.00");
+        assert_eq!(distance_label(3.2, 4.0), "3.00-3.25");
+        assert_eq!(distance_label(4.5, 4.0), ">4.00");
+    }
+    #[test]
+    fn test_breakdown() {
+        let contents = "1 0:1 1:2 3:5 4:0 5:1.09\n\
+                        0 0:1 1:3 3:5 4:0 5:2.15 # comment\n\
+                        \n\
+                        2 0:1 1:4 3:7 4:5 5:1.2\n";
+        let rows = libsvm_columns(contents, &[ATA_COLUMN, ATB_COLUMN, DISTAB_COLUMN]).unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec![5.0, 0.0, 1.09],
+                vec![5.0, 0.0, 2.15],
+                vec![7.0, 5.0, 1.2]
+            ]
+        );
+        let groups = breakdown(contents, &[1.0, 0.0, 2.0], &[1.0, 1.0, 1.0], 3.0).unwrap();
+        assert_eq!(groups[0].group, "C-H");
+        assert_eq!(groups[0].errors, 1);
+        assert_eq!(groups[0].bonds, 1);
+        assert_eq!(groups[1].group, "O-C");
+        assert_eq!(groups.iter().filter(|g| g.kind == "distance").count(), 3);
+        assert!(breakdown_csv(&groups).contains("pair,C-H,2,1,1,0.5\n"));
+        assert!(breakdown(contents, &[1.0], &[1.0], 3.0).is_err());
+    }
+}
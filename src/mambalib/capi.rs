@@ -0,0 +1,295 @@
+//! C API, enabled with the `capi` feature.
+//!
+//! The header `include/mamba.h` is generated by cbindgen during the build.
+//! All functions returning pointers return NULL on failure, the others -1
+//! (0 on success), the reason can be queried with `mamba_last_error`. NULL
+//! pointers are reported as errors. Atom indices are zero based.
+//! `tests/test_capi.c` exercises the header.
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use ndarray::Array2;
+use xgboost::Booster;
+
+use crate::ml::{load_model_with_config, predict_with_config};
+use crate::valence::postprocess;
+use crate::{bond_orders, FeatureConfig, Float, XYZMolecule};
+
+/// Return value of a successful call
+const MAMBA_OK: i32 = 0;
+/// Return value of a failed call, see `mamba_last_error`
+const MAMBA_ERROR: i32 = -1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_last_error(msg: &str) {
+    let msg = CString::new(msg.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = msg);
+}
+
+fn null_argument() -> i32 {
+    set_last_error("NULL argument");
+    MAMBA_ERROR
+}
+
+/// Bond predictor keeping the xgboost model loaded
+pub struct MambaPredictor {
+    booster: Booster,
+    config: FeatureConfig,
+}
+
+/// Perceived bonds, formal charges and radicals of a molecule
+pub struct MambaResult {
+    bonds: Vec<u32>,
+    orders: Vec<u32>,
+    charges: Vec<i32>,
+    radicals: Vec<u32>,
+}
+
+/// Message of the last error on this thread, valid until the next failing call
+#[no_mangle]
+pub extern "C" fn mamba_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ptr())
+}
+
+/// Loads the model, returns NULL on failure.
+///
+/// # Safety
+/// `model_path` must be a valid null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mamba_predictor_new(model_path: *const c_char) -> *mut MambaPredictor {
+    if model_path.is_null() {
+        set_last_error("model path is NULL");
+        return ptr::null_mut();
+    }
+    let path = CStr::from_ptr(model_path).to_string_lossy();
+    match panic::catch_unwind(|| load_model_with_config(&path)) {
+        Ok(Ok((booster, config))) => Box::into_raw(Box::new(MambaPredictor { booster, config })),
+        Ok(Err(e)) => {
+            set_last_error(&e.to_string());
+            ptr::null_mut()
+        }
+        Err(_) => {
+            set_last_error("loading the model panicked");
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Frees a predictor created by `mamba_predictor_new`.
+///
+/// # Safety
+/// `predictor` must be NULL or returned by `mamba_predictor_new` and not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn mamba_predictor_free(predictor: *mut MambaPredictor) {
+    if !predictor.is_null() {
+        drop(Box::from_raw(predictor));
+    }
+}
+
+/// Perceives the bonds of a molecule with `natoms` element symbols and
+/// `3 * natoms` coordinates in Angstrom, returns NULL on failure, e.g. for a
+/// NULL element symbol.
+///
+/// # Safety
+/// `predictor` must be a valid predictor, `elements` must point to `natoms`
+/// null terminated strings and `coords` to `3 * natoms` floats.
+#[no_mangle]
+pub unsafe extern "C" fn mamba_perceive(
+    predictor: *const MambaPredictor,
+    elements: *const *const c_char,
+    coords: *const f32,
+    natoms: usize,
+    charge: i32,
+    multiplicity: u32,
+) -> *mut MambaResult {
+    if predictor.is_null() || elements.is_null() || coords.is_null() {
+        set_last_error("NULL argument");
+        return ptr::null_mut();
+    }
+    let MambaPredictor { booster, config } = &*predictor;
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let ncoords = natoms.checked_mul(3).ok_or("Too many atoms")?;
+        let elements = slice::from_raw_parts(elements, natoms);
+        if let Some(k) = elements.iter().position(|e| e.is_null()) {
+            return Err(format!("Element symbol {} is NULL", k).into());
+        }
+        let atoms: Vec<String> = elements
+            .iter()
+            .map(|e| CStr::from_ptr(*e).to_string_lossy().into_owned())
+            .collect();
+        let xyz: Vec<Float> = slice::from_raw_parts(coords, ncoords)
+            .iter()
+            .map(|x| *x as Float)
+            .collect();
+        let coords = Array2::from_shape_vec((natoms, 3), xyz)?;
+        let mut mol = XYZMolecule::new(atoms, coords, charge);
+        mol.multiplicity = multiplicity;
+        let df = predict_with_config(booster, &mol, config)?;
+        let df = postprocess(&mut mol, df)?;
+        let mut result = MambaResult {
+            bonds: Vec::new(),
+            orders: Vec::new(),
+            charges: mol.charges,
+            radicals: mol.radicals,
+        };
+        for (i, j, order) in bond_orders(&df)? {
+            if order > 0 {
+                result.bonds.push(i.min(j) as u32);
+                result.bonds.push(i.max(j) as u32);
+                result.orders.push(order);
+            }
+        }
+        Ok::<MambaResult, Box<dyn Error>>(result)
+    }));
+    match result {
+        Ok(Ok(result)) => Box::into_raw(Box::new(result)),
+        Ok(Err(e)) => {
+            set_last_error(&e.to_string());
+            ptr::null_mut()
+        }
+        Err(_) => {
+            set_last_error("bond perception panicked");
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Number of perceived bonds, 0 if `result` is NULL.
+///
+/// # Safety
+/// `result` must be NULL or a valid result of `mamba_perceive`.
+#[no_mangle]
+pub unsafe extern "C" fn mamba_result_num_bonds(result: *const MambaResult) -> usize {
+    match result.as_ref() {
+        Some(result) => result.orders.len(),
+        None => {
+            null_argument();
+            0
+        }
+    }
+}
+
+/// Copies the bonds into `atoms` (`2 * num_bonds` pairs of atom indices) and `orders` (`num_bonds`).
+///
+/// Orders are 1 (single), 2 (double), 3 (triple) and 4 (aromatic).
+/// Returns 0, or -1 if a pointer is NULL.
+///
+/// # Safety
+/// `result` must be NULL or a valid result, the buffers must have the sizes given above.
+#[no_mangle]
+pub unsafe extern "C" fn mamba_result_bonds(
+    result: *const MambaResult,
+    atoms: *mut u32,
+    orders: *mut u32,
+) -> i32 {
+    match result.as_ref() {
+        Some(result) if !atoms.is_null() && !orders.is_null() => {
+            ptr::copy_nonoverlapping(result.bonds.as_ptr(), atoms, result.bonds.len());
+            ptr::copy_nonoverlapping(result.orders.as_ptr(), orders, result.orders.len());
+            MAMBA_OK
+        }
+        _ => null_argument(),
+    }
+}
+
+/// Copies the formal charges into `charges` (`natoms` values).
+/// Returns 0, or -1 if a pointer is NULL.
+///
+/// # Safety
+/// `result` must be NULL or a valid result, `charges` must hold `natoms` values.
+#[no_mangle]
+pub unsafe extern "C" fn mamba_result_formal_charges(
+    result: *const MambaResult,
+    charges: *mut i32,
+) -> i32 {
+    match result.as_ref() {
+        Some(result) if !charges.is_null() => {
+            ptr::copy_nonoverlapping(result.charges.as_ptr(), charges, result.charges.len());
+            MAMBA_OK
+        }
+        _ => null_argument(),
+    }
+}
+
+/// Copies the number of unpaired electrons per atom into `radicals` (`natoms` values).
+/// Returns 0, or -1 if a pointer is NULL.
+///
+/// # Safety
+/// `result` must be NULL or a valid result, `radicals` must hold `natoms` values.
+#[no_mangle]
+pub unsafe extern "C" fn mamba_result_radicals(
+    result: *const MambaResult,
+    radicals: *mut u32,
+) -> i32 {
+    match result.as_ref() {
+        Some(result) if !radicals.is_null() => {
+            ptr::copy_nonoverlapping(result.radicals.as_ptr(), radicals, result.radicals.len());
+            MAMBA_OK
+        }
+        _ => null_argument(),
+    }
+}
+
+/// Frees a result of `mamba_perceive`.
+///
+/// # Safety
+/// `result` must be NULL or returned by `mamba_perceive` and not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn mamba_result_free(result: *mut MambaResult) {
+    if !result.is_null() {
+        drop(Box::from_raw(result));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_null_arguments() {
+        unsafe {
+            assert!(mamba_predictor_new(ptr::null()).is_null());
+            let path = CString::new("xgb.model").unwrap();
+            let predictor = mamba_predictor_new(path.as_ptr());
+            assert!(!predictor.is_null());
+            let (c, o) = (CString::new("C").unwrap(), CString::new("O").unwrap());
+            let coords: [f32; 6] = [0.0, 0.0, 0.0, 0.0, 0.0, 1.13];
+            let elements = [c.as_ptr(), ptr::null()];
+            let result = mamba_perceive(predictor, elements.as_ptr(), coords.as_ptr(), 2, 0, 1);
+            assert!(result.is_null());
+            let error = CStr::from_ptr(mamba_last_error()).to_str().unwrap();
+            assert_eq!(error, "Element symbol 1 is NULL");
+            let elements = [c.as_ptr(), o.as_ptr()];
+            let result = mamba_perceive(predictor, elements.as_ptr(), coords.as_ptr(), 2, 0, 1);
+            assert!(!result.is_null());
+            let n = mamba_result_num_bonds(result);
+            let (mut atoms, mut orders) = (vec![0; 2 * n], vec![0; n]);
+            assert_eq!(
+                mamba_result_bonds(result, atoms.as_mut_ptr(), orders.as_mut_ptr()),
+                MAMBA_OK
+            );
+            assert_eq!(atoms, vec![0, 1]);
+            assert_eq!(
+                mamba_result_bonds(result, ptr::null_mut(), orders.as_mut_ptr()),
+                MAMBA_ERROR
+            );
+            assert_eq!(
+                mamba_result_formal_charges(ptr::null(), ptr::null_mut()),
+                MAMBA_ERROR
+            );
+            assert_eq!(mamba_result_num_bonds(ptr::null()), 0);
+            mamba_result_free(result);
+            mamba_predictor_free(predictor);
+        }
+    }
+}
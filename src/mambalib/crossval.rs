@@ -0,0 +1,229 @@
+//! K-fold cross-validation with the rows of a molecule kept in one fold.
+//!
+//! Rows of the same molecule are highly correlated, random row splits would
+//! overestimate the accuracy. The molecules are taken from the `qid` of the
+//! libsvm rows (see `dataset`), shuffled and distributed over the folds.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use xgboost::DMatrix;
+
+use crate::dataset::libsvm_qids;
+use crate::metrics::{format_metric, ClassificationReport, ConfusionMatrix, BOND_CLASSES};
+use crate::ml::{train_booster, TrainParams};
+use crate::Float;
+
+/// Metrics of the model trained without one fold, evaluated on that fold
+pub struct FoldResult {
+    pub fold: usize,
+    pub train_rows: usize,
+    pub test_rows: usize,
+    pub report: ClassificationReport,
+}
+
+/// Prediction of a row by the model which has not seen its molecule
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutOfFold {
+    pub row: usize,
+    pub qid: usize,
+    pub fold: usize,
+    pub label: u32,
+    pub pred: u32,
+}
+
+/// Results of all folds
+pub struct CrossValidation {
+    pub folds: Vec<FoldResult>,
+    /// out-of-fold predictions in the row order of the data set
+    pub oof: Vec<OutOfFold>,
+}
+
+/// Fold of every row, all rows of a molecule get the same fold
+pub fn assign_folds(qids: &[usize], k: usize, seed: u64) -> Vec<usize> {
+    let mut molecules: Vec<usize> = qids.to_vec();
+    molecules.sort_unstable();
+    molecules.dedup();
+    molecules.shuffle(&mut StdRng::seed_from_u64(seed));
+    let fold_of: BTreeMap<usize, usize> = molecules
+        .iter()
+        .enumerate()
+        .map(|(i, qid)| (*qid, i % k.max(1)))
+        .collect();
+    qids.iter().map(|qid| fold_of[qid]).collect()
+}
+
+/// Data set and molecule ids of a libsvm file
+pub fn load_grouped(path: &str) -> Result<(DMatrix, Vec<usize>), Box<dyn Error>> {
+    let qids = libsvm_qids(&fs::read_to_string(path)?)?;
+    let dall = DMatrix::load(path)?;
+    if qids.len() != dall.num_rows() {
+        return Err(format!("{} molecule ids for {} rows", qids.len(), dall.num_rows()).into());
+    }
+    Ok((dall, qids))
+}
+
+/// Trains `k` models on a libsvm file with molecule ids and evaluates each on its held out fold
+pub fn cross_validate(
+    path: &str,
+    k: usize,
+    params: &TrainParams,
+    seed: u64,
+) -> Result<CrossValidation, Box<dyn Error>> {
+    let (dall, qids) = load_grouped(path)?;
+    cross_validate_matrix(&dall, &qids, k, params, seed)
+}
+
+/// Cross-validation of an already loaded data set with the molecule id of every row
+pub fn cross_validate_matrix(
+    dall: &DMatrix,
+    qids: &[usize],
+    k: usize,
+    params: &TrainParams,
+    seed: u64,
+) -> Result<CrossValidation, Box<dyn Error>> {
+    let nmols = {
+        let mut m = qids.to_vec();
+        m.sort_unstable();
+        m.dedup();
+        m.len()
+    };
+    if k < 2 || k > nmols {
+        return Err(format!("Need 2 to {} folds (one per molecule), got {}", nmols, k).into());
+    }
+    let folds = assign_folds(qids, k, seed);
+    let labels = dall.get_labels()?.to_vec();
+    let mut results = Vec::<FoldResult>::new();
+    let mut oof = Vec::<OutOfFold>::new();
+    for fold in 0..k {
+        let (test, train): (Vec<usize>, Vec<usize>) =
+            (0..qids.len()).partition(|&row| folds[row] == fold);
+        let dtrain = dall.slice(&train)?;
+        let dtest = dall.slice(&test)?;
+        eprintln!(
+            "Fold {}: training on {} rows, testing on {} rows",
+            fold + 1,
+            train.len(),
+            test.len()
+        );
+        let booster = train_booster(&dtrain, params, None)?;
+        let preds = booster.predict(&dtest)?;
+        let test_labels: Vec<f32> = test.iter().map(|&row| labels[row]).collect();
+        let cm = ConfusionMatrix::from_labels(&test_labels, &preds, BOND_CLASSES.len());
+        for (row, pred) in test.iter().zip(preds.iter()) {
+            oof.push(OutOfFold {
+                row: *row,
+                qid: qids[*row],
+                fold,
+                label: labels[*row] as u32,
+                pred: *pred as u32,
+            });
+        }
+        results.push(FoldResult {
+            fold,
+            train_rows: train.len(),
+            test_rows: test.len(),
+            report: ClassificationReport::new(&cm, BOND_CLASSES),
+        });
+    }
+    oof.sort_by_key(|o| o.row);
+    Ok(CrossValidation {
+        folds: results,
+        oof,
+    })
+}
+
+/// Mean and standard deviation of the defined values
+pub fn mean_std(values: &[Option<Float>]) -> Option<(Float, Float)> {
+    let defined: Vec<Float> = values.iter().flatten().cloned().collect();
+    if defined.is_empty() {
+        return None;
+    }
+    let n = defined.len() as Float;
+    let mean = defined.iter().sum::<Float>() / n;
+    let var = defined.iter().map(|v| (v - mean).powi(2)).sum::<Float>() / n;
+    Some((mean, var.sqrt()))
+}
+
+impl CrossValidation {
+    /// Mean and standard deviation over the folds of the main metrics
+    pub fn summary(&self) -> Vec<(&'static str, Option<(Float, Float)>)> {
+        let metric = |f: fn(&ClassificationReport) -> Option<Float>| {
+            let values: Vec<Option<Float>> = self.folds.iter().map(|r| f(&r.report)).collect();
+            mean_std(&values)
+        };
+        vec![
+            ("accuracy", metric(|r| r.accuracy)),
+            ("kappa", metric(|r| r.kappa)),
+            ("macro_precision", metric(|r| r.macro_precision)),
+            ("macro_recall", metric(|r| r.macro_recall)),
+            ("macro_f1", metric(|r| r.macro_f1)),
+        ]
+    }
+
+    /// Metrics of every fold and their mean and standard deviation as text table
+    pub fn table(&self) -> String {
+        let mut table = format!(
+            "{:>10}{:>10}{:>10}{:>10}{:>10}\n",
+            "fold", "rows", "accuracy", "kappa", "macro_f1"
+        );
+        for r in &self.folds {
+            table += format!(
+                "{:>10}{:>10}{:>10}{:>10}{:>10}\n",
+                r.fold + 1,
+                r.test_rows,
+                format_metric(r.report.accuracy),
+                format_metric(r.report.kappa),
+                format_metric(r.report.macro_f1)
+            )
+            .as_str();
+        }
+        table += "\n";
+        for (name, value) in self.summary() {
+            let value = value.map_or("-".to_owned(), |(m, s)| format!("{:.3} +- {:.3}", m, s));
+            table += format!("{:>16}: {}\n", name, value).as_str();
+        }
+        table
+    }
+
+    /// Out-of-fold predictions as CSV
+    pub fn oof_csv(&self) -> String {
+        let mut csv = String::from("row,qid,fold,label,pred\n");
+        for o in &self.oof {
+            csv += format!("{},{},{},{},{}\n", o.row, o.qid, o.fold, o.label, o.pred).as_str();
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assign_folds() {
+        let qids = vec![3, 3, 3, 8, 8, 1, 1, 1, 1, 5];
+        let folds = assign_folds(&qids, 2, 42);
+        assert_eq!(folds, assign_folds(&qids, 2, 42));
+        assert!(folds[0] == folds[1] && folds[1] == folds[2]);
+        assert_eq!(folds[3], folds[4]);
+        for fold in 0..2 {
+            let mols: Vec<usize> = (0..qids.len())
+                .filter(|&r| folds[r] == fold)
+                .map(|r| qids[r])
+                .collect();
+            assert!(!mols.is_empty());
+        }
+    }
+    #[test]
+    fn test_mean_std() {
+        let (mean, std) = mean_std(&[Some(0.5), None, Some(1.0)]).unwrap();
+        assert!((mean - 0.75).abs() < 1e-6);
+        assert!((std - 0.25).abs() < 1e-6);
+        assert_eq!(mean_std(&[None]), None);
+    }
+}
@@ -0,0 +1,95 @@
+//! Training data in libsvm format.
+//!
+//! Rows are the feature table of `create_dataframe` with the bond order of a
+//! reference SD file as label. Features are zero based in the column order of the
+//! feature table, as in the prediction, zeros are left out as in the dense
+//! prediction matrix. The molecule of every row is recorded as `qid`, so rows of
+//! one molecule can be kept together when splitting the data.
+
+use std::error::Error;
+
+use polars::prelude::*;
+
+use crate::benchmark::BondMap;
+use crate::Float;
+
+/// Bond order of the reference for every row of the feature table, 0 for no bond
+pub fn reference_labels(df: &DataFrame, reference: &BondMap) -> Result<Vec<u32>, Box<dyn Error>> {
+    let id1 = df.column("id1")?.f32()?;
+    let id2 = df.column("id2")?.f32()?;
+    let labels = id1
+        .into_iter()
+        .zip(id2.into_iter())
+        .map(|(a, b)| {
+            let a = a.unwrap_or(1.0) as usize - 1;
+            let b = b.unwrap_or(1.0) as usize - 1;
+            reference.get(&(a.min(b), a.max(b))).cloned().unwrap_or(0)
+        })
+        .collect();
+    Ok(labels)
+}
+
+/// Rows of the feature table in libsvm format with the molecule as `qid`
+pub fn libsvm_rows(df: &DataFrame, labels: &[u32], qid: usize) -> Result<String, Box<dyn Error>> {
+    if labels.len() != df.height() {
+        return Err(format!("{} labels for {} rows", labels.len(), df.height()).into());
+    }
+    let columns: Vec<Vec<Option<Float>>> = df
+        .get_columns()
+        .iter()
+        .map(|s| Ok(s.f32()?.into_iter().collect()))
+        .collect::<Result<_, PolarsError>>()?;
+    let mut rows = String::new();
+    for (i, label) in labels.iter().enumerate() {
+        rows += format!("{} qid:{}", label, qid).as_str();
+        for (k, column) in columns.iter().enumerate() {
+            match column[i] {
+                Some(v) if v != 0.0 => rows += format!(" {}:{}", k, v).as_str(),
+                _ => {}
+            }
+        }
+        rows += "\n";
+    }
+    Ok(rows)
+}
+
+/// Molecule (`qid`) of every row of a libsvm file, rows without one are an error
+pub fn libsvm_qids(contents: &str) -> Result<Vec<usize>, Box<dyn Error>> {
+    let mut qids = Vec::<usize>::new();
+    for (n, line) in contents.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        if line.trim().is_empty() {
+            continue;
+        }
+        let qid = line
+            .split_whitespace()
+            .find_map(|t| t.strip_prefix("qid:"))
+            .ok_or_else(|| format!("No qid (molecule id) in line {}", n + 1))?;
+        qids.push(qid.parse()?);
+    }
+    Ok(qids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_libsvm_rows() {
+        let df = DataFrame::new(vec![
+            Series::new("id1", vec![1.0 as Float, 3.0]),
+            Series::new("id2", vec![2.0 as Float, 1.0]),
+            Series::new("q", vec![0.0 as Float, 0.0]),
+            Series::new("distab", vec![1.2 as Float, 2.5]),
+        ])
+        .unwrap();
+        let mut reference = BondMap::new();
+        reference.insert((0, 1), 2);
+        let labels = reference_labels(&df, &reference).unwrap();
+        assert_eq!(labels, vec![2, 0]);
+        let rows = libsvm_rows(&df, &labels, 7).unwrap();
+        assert_eq!(rows, "2 qid:7 0:1 1:2 3:1.2\n0 qid:7 0:3 1:1 3:2.5\n");
+        assert_eq!(libsvm_qids(&rows).unwrap(), vec![7, 7]);
+        assert!(libsvm_qids("1 0:1 1:2\n").is_err());
+    }
+}
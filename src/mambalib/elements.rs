@@ -0,0 +1,239 @@
+//! Element symbols and properties.
+//!
+//! Symbols in input files come in many flavours: upper or lower case (`CL`, `cl`),
+//! atomic numbers (`6`) or atom labels of CIF-derived files (`C12`, `H3A`).
+//! They are parsed into an `Element`, truly unknown symbols are an error.
+
+use std::error::Error;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::Float;
+
+/// Symbols of the periodic table, index is the atomic number - 1
+pub static SYMBOLS: &[&str] = &[
+    "H", "He", "Li", "Be", "B", "C", "N", "O", "F", "Ne", "Na", "Mg", "Al", "Si", "P", "S", "Cl",
+    "Ar", "K", "Ca", "Sc", "Ti", "V", "Cr", "Mn", "Fe", "Co", "Ni", "Cu", "Zn", "Ga", "Ge", "As",
+    "Se", "Br", "Kr", "Rb", "Sr", "Y", "Zr", "Nb", "Mo", "Tc", "Ru", "Rh", "Pd", "Ag", "Cd", "In",
+    "Sn", "Sb", "Te", "I", "Xe", "Cs", "Ba", "La", "Ce", "Pr", "Nd", "Pm", "Sm", "Eu", "Gd", "Tb",
+    "Dy", "Ho", "Er", "Tm", "Yb", "Lu", "Hf", "Ta", "W", "Re", "Os", "Ir", "Pt", "Au", "Hg", "Tl",
+    "Pb", "Bi", "Po", "At", "Rn", "Fr", "Ra", "Ac", "Th", "Pa", "U", "Np", "Pu", "Am", "Cm", "Bk",
+    "Cf", "Es", "Fm", "Md", "No", "Lr", "Rf", "Db", "Sg", "Bh", "Hs", "Mt", "Ds", "Rg", "Cn", "Nh",
+    "Fl", "Mc", "Lv", "Ts", "Og",
+];
+
+/// Chemical element, identified by its atomic number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct Element {
+    atomic_number: u8,
+}
+
+impl Element {
+    pub fn from_atomic_number(z: usize) -> Result<Self, Box<dyn Error>> {
+        if z == 0 || z > SYMBOLS.len() {
+            return Err(format!("Invalid atomic number: {}", z).into());
+        }
+        Ok(Element {
+            atomic_number: z as u8,
+        })
+    }
+
+    /// Element of a correctly capitalized symbol, e.g. `Cl`
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        SYMBOLS.iter().position(|&s| s == symbol).map(|i| Element {
+            atomic_number: i as u8 + 1,
+        })
+    }
+
+    pub fn atomic_number(&self) -> usize {
+        self.atomic_number as usize
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        SYMBOLS[self.atomic_number as usize - 1]
+    }
+}
+
+impl fmt::Display for Element {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+/// First letter upper case, the rest lower case
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().collect::<String>() + chars.as_str().to_lowercase().as_str(),
+        None => String::new(),
+    }
+}
+
+/// Parses an element from a symbol, an atomic number or an atom label
+///
+/// Labels are reduced to their leading letters, e.g. `C12` or `H3A`. A correctly
+/// capitalized two letter symbol is taken as written (`Ca1` is calcium), as are
+/// exactly two letters in one case (`CL`, `cl`), otherwise the first letter is the
+/// element (`HA` or `CAB` are hydrogen and carbon).
+pub fn parse_element(label: &str) -> Result<Element, Box<dyn Error>> {
+    let label = label.trim();
+    if !label.is_empty() && label.chars().all(|c| c.is_ascii_digit()) {
+        return Element::from_atomic_number(label.parse()?);
+    }
+    let letters: String = label
+        .chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect();
+    let unknown = || format!("Unknown element symbol: {}", label);
+    if letters.is_empty() {
+        return Err(unknown().into());
+    }
+    if letters.len() >= 2 {
+        if let Some(element) = Element::from_symbol(&letters[..2]) {
+            return Ok(element);
+        }
+        let same_case = letters.chars().all(|c| c.is_ascii_uppercase())
+            || letters.chars().all(|c| c.is_ascii_lowercase());
+        if letters.len() == 2 && same_case {
+            if let Some(element) = Element::from_symbol(&capitalize(&letters)) {
+                return Ok(element);
+            }
+        }
+    }
+    Element::from_symbol(&letters[..1].to_uppercase()).ok_or_else(|| unknown().into())
+}
+
+/// Single bond covalent radius in Angstrom (Cordero et al., Dalton Trans. 2008)
+///
+/// Low spin values for Mn, Fe and Co, sp3 carbon.
+pub fn covalent_radius(symbol: &str) -> Option<Float> {
+    let r = match symbol {
+        "H" => 0.31,
+        "He" => 0.28,
+        "Li" => 1.28,
+        "Be" => 0.96,
+        "B" => 0.84,
+        "C" => 0.76,
+        "N" => 0.71,
+        "O" => 0.66,
+        "F" => 0.57,
+        "Ne" => 0.58,
+        "Na" => 1.66,
+        "Mg" => 1.41,
+        "Al" => 1.21,
+        "Si" => 1.11,
+        "P" => 1.07,
+        "S" => 1.05,
+        "Cl" => 1.02,
+        "Ar" => 1.06,
+        "K" => 2.03,
+        "Ca" => 1.76,
+        "Sc" => 1.70,
+        "Ti" => 1.60,
+        "V" => 1.53,
+        "Cr" => 1.39,
+        "Mn" => 1.39,
+        "Fe" => 1.32,
+        "Co" => 1.26,
+        "Ni" => 1.24,
+        "Cu" => 1.32,
+        "Zn" => 1.22,
+        "Ga" => 1.22,
+        "Ge" => 1.20,
+        "As" => 1.19,
+        "Se" => 1.20,
+        "Br" => 1.20,
+        "Kr" => 1.16,
+        "Rb" => 2.20,
+        "Sr" => 1.95,
+        "Y" => 1.90,
+        "Zr" => 1.75,
+        "Nb" => 1.64,
+        "Mo" => 1.54,
+        "Tc" => 1.47,
+        "Ru" => 1.46,
+        "Rh" => 1.42,
+        "Pd" => 1.39,
+        "Ag" => 1.45,
+        "Cd" => 1.44,
+        "In" => 1.42,
+        "Sn" => 1.39,
+        "Sb" => 1.39,
+        "Te" => 1.38,
+        "I" => 1.39,
+        "Xe" => 1.40,
+        "Cs" => 2.44,
+        "Ba" => 2.15,
+        "La" => 2.07,
+        "Ce" => 2.04,
+        "Pr" => 2.03,
+        "Nd" => 2.01,
+        "Pm" => 1.99,
+        "Sm" => 1.98,
+        "Eu" => 1.98,
+        "Gd" => 1.96,
+        "Tb" => 1.94,
+        "Dy" => 1.92,
+        "Ho" => 1.92,
+        "Er" => 1.89,
+        "Tm" => 1.90,
+        "Yb" => 1.87,
+        "Lu" => 1.87,
+        "Hf" => 1.75,
+        "Ta" => 1.70,
+        "W" => 1.62,
+        "Re" => 1.51,
+        "Os" => 1.44,
+        "Ir" => 1.41,
+        "Pt" => 1.36,
+        "Au" => 1.36,
+        "Hg" => 1.32,
+        "Tl" => 1.45,
+        "Pb" => 1.46,
+        "Bi" => 1.48,
+        "Po" => 1.40,
+        "At" => 1.50,
+        "Rn" => 1.50,
+        "Fr" => 2.60,
+        "Ra" => 2.21,
+        "Ac" => 2.15,
+        "Th" => 2.06,
+        "Pa" => 2.00,
+        "U" => 1.96,
+        "Np" => 1.90,
+        "Pu" => 1.87,
+        "Am" => 1.80,
+        "Cm" => 1.69,
+        _ => return None,
+    };
+    Some(r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(label: &str) -> &'static str {
+        parse_element(label).unwrap().symbol()
+    }
+
+    #[test]
+    fn test_parse_element() {
+        assert_eq!(symbol("Cl"), "Cl");
+        assert_eq!(symbol("CL"), "Cl");
+        assert_eq!(symbol("cl"), "Cl");
+        assert_eq!(symbol("C1"), "C");
+        assert_eq!(symbol("C12"), "C");
+        assert_eq!(symbol("H3A"), "H");
+        assert_eq!(symbol("Ca1"), "Ca");
+        assert_eq!(symbol("HA"), "H");
+        assert_eq!(symbol("CAB"), "C");
+        assert_eq!(symbol("6"), "C");
+        assert_eq!(symbol("84"), "Po");
+        assert!(parse_element("0").is_err());
+        assert!(parse_element("119").is_err());
+        assert!(parse_element("Xx").is_err());
+        assert!(parse_element("12C").is_err());
+    }
+}
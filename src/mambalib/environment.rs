@@ -0,0 +1,164 @@
+//! Local environment descriptors of the atoms of a pair.
+//!
+//! The hybridisation of an atom, and with it the order of its bonds, shows in the
+//! number of its covalently bonded neighbors: a carbon with four neighbors has
+//! only single bonds, one with three has a double or aromatic bond. Per atom
+//! these are the coordination number (neighbors within the covalent radii sum
+//! plus `DEFAULT_TOLERANCE`), split into heavy atoms and hydrogens, and
+//! optionally smooth radial distribution functions, Gaussians of the neighbor
+//! distances damped to zero at the distance cut off.
+
+use std::error::Error;
+
+use ndarray::Array2;
+
+use crate::elements::covalent_radius;
+use crate::radii::DEFAULT_TOLERANCE;
+use crate::{FeatureConfig, Float, XYZMolecule};
+
+/// Centers of the radial distribution functions in Angstrom
+pub const RADIAL_CENTERS: [Float; 4] = [1.0, 1.5, 2.0, 2.5];
+/// Width parameter of the radial Gaussians exp(-w (r - center)^2) in 1/Angstrom^2
+pub const RADIAL_WIDTH: Float = 4.0;
+
+/// Descriptors of every atom of a molecule
+pub struct Environment {
+    coordination: Vec<Float>,
+    heavy: Vec<Float>,
+    hydrogens: Vec<Float>,
+    radial: Vec<[Float; 4]>,
+}
+
+impl Environment {
+    /// Descriptors from the distance matrix of the molecule
+    pub fn new(
+        mol: &XYZMolecule,
+        dm: &Array2<Float>,
+        config: &FeatureConfig,
+    ) -> Result<Self, Box<dyn Error>> {
+        let radii = mol
+            .atoms
+            .iter()
+            .map(|a| {
+                covalent_radius(a).ok_or_else(|| format!("No covalent radius for element {}", a))
+            })
+            .collect::<Result<Vec<Float>, String>>()?;
+        let n = mol.natoms;
+        let mut env = Environment {
+            coordination: vec![0.0; n],
+            heavy: vec![0.0; n],
+            hydrogens: vec![0.0; n],
+            radial: vec![[0.0; 4]; n],
+        };
+        for i in 0..n {
+            for j in 0..n {
+                if i == j {
+                    continue;
+                }
+                let dist = dm[[i, j]];
+                if dist < radii[i] + radii[j] + DEFAULT_TOLERANCE {
+                    env.coordination[i] += 1.0;
+                    if mol.atoms[j] == "H" {
+                        env.hydrogens[i] += 1.0;
+                    } else {
+                        env.heavy[i] += 1.0;
+                    }
+                }
+                if dist < config.dist_cutoff {
+                    let damping =
+                        0.5 * ((std::f32::consts::PI * dist / config.dist_cutoff).cos() + 1.0);
+                    for (g, center) in env.radial[i].iter_mut().zip(RADIAL_CENTERS) {
+                        *g += (-RADIAL_WIDTH * (dist - center).powi(2)).exp() * damping;
+                    }
+                }
+            }
+        }
+        Ok(env)
+    }
+
+    /// Descriptors of both atoms of the pair `a`-`b` with their column names
+    pub fn pair_features(
+        &self,
+        a: usize,
+        b: usize,
+        config: &FeatureConfig,
+    ) -> Vec<(String, Float)> {
+        let mut features = Vec::<(String, Float)>::new();
+        if config.environment {
+            for (name, values) in [
+                ("cn", &self.coordination),
+                ("heavy", &self.heavy),
+                ("hyd", &self.hydrogens),
+            ] {
+                features.push((format!("{}a", name), values[a]));
+                features.push((format!("{}b", name), values[b]));
+            }
+        }
+        if config.radial {
+            for (label, atom) in [("a", a), ("b", b)] {
+                for (k, g) in self.radial[atom].iter().enumerate() {
+                    features.push((format!("rdf{}{}", label, k + 1), *g));
+                }
+            }
+        }
+        features
+    }
+}
+
+/// Column names of the environment descriptors of a config
+pub fn environment_names(config: &FeatureConfig) -> Vec<String> {
+    let mut names = Vec::<String>::new();
+    if config.environment {
+        for name in ["cn", "heavy", "hyd"] {
+            names.push(format!("{}a", name));
+            names.push(format!("{}b", name));
+        }
+    }
+    if config.radial {
+        for label in ["a", "b"] {
+            names.extend((1..=RADIAL_CENTERS.len()).map(|k| format!("rdf{}{}", label, k)));
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+
+    use super::*;
+
+    #[test]
+    fn test_environment() {
+        // methanol
+        let atoms = ["C", "O", "H", "H", "H", "H"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let coords = arr2(&[
+            [0.0, 0.0, 0.0],
+            [1.43, 0.0, 0.0],
+            [-0.36, 1.03, 0.0],
+            [-0.36, -0.51, 0.89],
+            [-0.36, -0.51, -0.89],
+            [1.75, 0.0, 0.9],
+        ]);
+        let mol = XYZMolecule::new(atoms, coords, 0);
+        let config = FeatureConfig {
+            environment: true,
+            radial: true,
+            ..Default::default()
+        };
+        let env = Environment::new(&mol, &mol.distance_matrix().unwrap(), &config).unwrap();
+        let features = env.pair_features(0, 1, &config);
+        let names: Vec<String> = features.iter().map(|(n, _)| n.clone()).collect();
+        assert_eq!(names, environment_names(&config));
+        let values: Vec<Float> = features.iter().map(|(_, v)| *v).collect();
+        assert_eq!(values[..6], [4.0, 2.0, 1.0, 1.0, 3.0, 1.0]);
+        // three hydrogens at 1.09 Angstrom around the carbon, one around the oxygen
+        assert!(features[6].1 > 2.0 * features[10].1);
+        let base = FeatureConfig::default();
+        assert!(env.pair_features(0, 1, &base).is_empty());
+        assert!(environment_names(&base).is_empty());
+    }
+}
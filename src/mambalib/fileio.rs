@@ -0,0 +1,113 @@
+//! Reading and writing files with transparent gzip/zstd compression.
+//!
+//! The compression is chosen from the file extension (`.gz`, `.zst`).
+
+use std::error::Error;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+
+/// Compression formats recognized by file extension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// File name suffix including the dot
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+}
+
+/// Path without a compression extension, e.g. `mol.xyz.gz` -> `mol.xyz`
+pub fn strip_compression<P: AsRef<Path>>(path: P) -> std::path::PathBuf {
+    let path = path.as_ref();
+    match Compression::from_path(path) {
+        Compression::None => path.to_path_buf(),
+        _ => path.with_extension(""),
+    }
+}
+
+/// Reads a whole file into a string, decompressing it if needed
+pub fn read_to_string<P: AsRef<Path>>(path: P) -> Result<String, Box<dyn Error>> {
+    let path = path.as_ref();
+    let mut contents = String::new();
+    match Compression::from_path(path) {
+        Compression::None => contents = fs::read_to_string(path)?,
+        Compression::Gzip => {
+            MultiGzDecoder::new(fs::File::open(path)?).read_to_string(&mut contents)?;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        Compression::Zstd => {
+            zstd::stream::read::Decoder::new(fs::File::open(path)?)?
+                .read_to_string(&mut contents)?;
+        }
+        #[cfg(target_arch = "wasm32")]
+        Compression::Zstd => return Err("zstd compression is not available on wasm32".into()),
+    }
+    Ok(contents)
+}
+
+/// Writes contents to a file, compressing it if needed
+pub fn write<P: AsRef<Path>>(path: P, contents: &[u8]) -> Result<(), Box<dyn Error>> {
+    let path = path.as_ref();
+    match Compression::from_path(path) {
+        Compression::None => fs::write(path, contents)?,
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(fs::File::create(path)?, flate2::Compression::default());
+            encoder.write_all(contents)?;
+            encoder.finish()?;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        Compression::Zstd => {
+            let mut encoder = zstd::stream::write::Encoder::new(fs::File::create(path)?, 0)?;
+            encoder.write_all(contents)?;
+            encoder.finish()?;
+        }
+        #[cfg(target_arch = "wasm32")]
+        Compression::Zstd => return Err("zstd compression is not available on wasm32".into()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression() {
+        assert_eq!(Compression::from_path("mol.xyz"), Compression::None);
+        assert_eq!(Compression::from_path("mol.xyz.gz"), Compression::Gzip);
+        assert_eq!(Compression::from_path("mol.sdf.zst"), Compression::Zstd);
+        assert_eq!(strip_compression("data/mol.xyz.gz"), Path::new("data/mol.xyz"));
+    }
+    #[test]
+    fn test_roundtrip() {
+        let contents = fs::read_to_string("data/test1.xyz").unwrap();
+        let dir = std::env::temp_dir();
+        for name in ["mamba_test1.xyz.gz", "mamba_test1.xyz.zst"] {
+            let path = dir.join(name);
+            write(&path, contents.as_bytes()).unwrap();
+            assert_eq!(read_to_string(&path).unwrap(), contents);
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
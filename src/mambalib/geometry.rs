@@ -0,0 +1,195 @@
+//! Angles, torsions and out-of-plane distances for the extended feature set.
+//!
+//! Bond orders show in the local geometry: atoms of double and aromatic bonds are
+//! planar (120 degree angles, no pyramidalisation) and the bond is flat across
+//! (torsions of 0 or 180 degrees). Vectors between atoms use the minimum image
+//! for periodic molecules, as the distances of the feature table.
+
+use std::error::Error;
+
+use ndarray::Array2;
+
+use crate::pbc::Cell;
+use crate::{Float, XYZMolecule};
+
+/// Coordinates of a molecule with its periodic cell, if any
+pub struct Geometry<'a> {
+    coords: &'a Array2<Float>,
+    cell: Option<Cell>,
+}
+
+impl<'a> Geometry<'a> {
+    pub fn new(mol: &'a XYZMolecule) -> Result<Self, Box<dyn Error>> {
+        Ok(Geometry {
+            coords: &mol.coords,
+            cell: mol.cell()?,
+        })
+    }
+
+    /// Vector from atom `i` to atom `j`
+    pub fn vector(&self, i: usize, j: usize) -> [Float; 3] {
+        let (a, b) = (self.coords.row(i), self.coords.row(j));
+        let diff = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+        match &self.cell {
+            Some(cell) => cell.minimum_image(diff),
+            None => diff,
+        }
+    }
+
+    /// Angle `i`-`j`-`k` at atom `j` in degrees, 0 for coinciding atoms
+    pub fn angle(&self, i: usize, j: usize, k: usize) -> Float {
+        angle_between(self.vector(j, i), self.vector(j, k))
+    }
+
+    /// Torsion `i`-`j`-`k`-`l` in degrees without sign (0 to 180), 0 if undefined
+    pub fn torsion(&self, i: usize, j: usize, k: usize, l: usize) -> Float {
+        let b1 = self.vector(i, j);
+        let b2 = self.vector(j, k);
+        let b3 = self.vector(k, l);
+        angle_between(cross(b1, b2), cross(b2, b3))
+    }
+
+    /// Distance of atom `center` from the plane through three other atoms, 0 if undefined
+    pub fn out_of_plane(&self, center: usize, plane: [usize; 3]) -> Float {
+        let u = plane.map(|p| self.vector(center, p));
+        let normal = cross(sub(u[1], u[0]), sub(u[2], u[0]));
+        let n = norm(normal);
+        if n < 1e-6 {
+            return 0.0;
+        }
+        dot(u[0], normal).abs() / n
+    }
+
+    /// Geometric features of the pair `a`-`b` with the nearest neighbors of both atoms:
+    /// neighbor-atom-atom angles, the pyramidalisation of both atoms (distance from
+    /// the plane of the other atom and its two nearest neighbors) and the torsion
+    /// of the nearest neighbors across the pair. There are `n_cut` angles per atom,
+    /// 0 for missing neighbors.
+    pub fn pair_features(
+        &self,
+        a: usize,
+        b: usize,
+        neighbors_a: &[usize],
+        neighbors_b: &[usize],
+        n_cut: usize,
+    ) -> Vec<(String, Float)> {
+        let mut features = Vec::<(String, Float)>::new();
+        for (label, center, other, neighbors) in
+            [("a", a, b, neighbors_a), ("b", b, a, neighbors_b)]
+        {
+            for k in 0..n_cut {
+                let name = format!("ang{}{}", label, k + 1);
+                let angle = neighbors
+                    .get(k)
+                    .map_or(0.0, |n| self.angle(*n, center, other));
+                features.push((name, angle));
+            }
+        }
+        for (label, center, other, neighbors) in
+            [("a", a, b, neighbors_a), ("b", b, a, neighbors_b)]
+        {
+            let pyramidalisation = match neighbors {
+                [n1, n2, ..] => self.out_of_plane(center, [other, *n1, *n2]),
+                _ => 0.0,
+            };
+            features.push((format!("pyr{}", label), pyramidalisation));
+        }
+        let torsion = match (neighbors_a.first(), neighbors_b.first()) {
+            (Some(na), Some(nb)) => self.torsion(*na, a, b, *nb),
+            _ => 0.0,
+        };
+        features.push(("torsab".to_owned(), torsion));
+        features
+    }
+}
+
+fn dot(a: [Float; 3], b: [Float; 3]) -> Float {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [Float; 3], b: [Float; 3]) -> [Float; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn sub(a: [Float; 3], b: [Float; 3]) -> [Float; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn norm(a: [Float; 3]) -> Float {
+    dot(a, a).sqrt()
+}
+
+/// Angle between two vectors in degrees, 0 if one of them vanishes
+fn angle_between(a: [Float; 3], b: [Float; 3]) -> Float {
+    let n = norm(a) * norm(b);
+    if n < 1e-12 {
+        return 0.0;
+    }
+    (dot(a, b) / n).clamp(-1.0, 1.0).acos().to_degrees()
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+
+    use super::*;
+
+    #[test]
+    fn test_geometry() {
+        // planar C=C with one H on the first and two H (cis and trans) on the second carbon
+        let atoms = ["C", "C", "H", "H", "H"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let coords = arr2(&[
+            [0.0, 0.0, 0.0],
+            [1.34, 0.0, 0.0],
+            [-0.5, 0.87, 0.0],
+            [1.84, 0.87, 0.0],
+            [1.84, -0.87, 0.0],
+        ]);
+        let mol = XYZMolecule::new(atoms, coords, 0);
+        let geom = Geometry::new(&mol).unwrap();
+        assert!((geom.angle(2, 0, 1) - 119.9).abs() < 0.5);
+        assert!(geom.torsion(2, 0, 1, 3).abs() < 0.1);
+        assert!((geom.torsion(2, 0, 1, 4) - 180.0).abs() < 0.1);
+        assert!(geom.out_of_plane(1, [0, 3, 4]) < 1e-6);
+        let features = geom.pair_features(0, 1, &[2], &[3, 4], 2);
+        let names: Vec<&str> = features.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["anga1", "anga2", "angb1", "angb2", "pyra", "pyrb", "torsab"]
+        );
+        assert_eq!(features[1].1, 0.0);
+        assert_eq!(features[4].1, 0.0);
+    }
+    #[test]
+    fn test_pyramidal() {
+        // ammonia, N 0.38 Angstrom above the plane of the hydrogens
+        let atoms = ["N", "H", "H", "H"].iter().map(|s| s.to_string()).collect();
+        let coords = arr2(&[
+            [0.0, 0.0, 0.38],
+            [0.94, 0.0, 0.0],
+            [-0.47, 0.814, 0.0],
+            [-0.47, -0.814, 0.0],
+        ]);
+        let mol = XYZMolecule::new(atoms, coords, 0);
+        let geom = Geometry::new(&mol).unwrap();
+        assert!((geom.out_of_plane(0, [1, 2, 3]) - 0.38).abs() < 1e-4);
+    }
+    #[test]
+    fn test_periodic_vector() {
+        let mut mol = XYZMolecule::new(
+            vec!["O".to_string(), "H".to_string()],
+            arr2(&[[0.2, 5.0, 5.0], [9.4, 5.0, 5.0]]),
+            0,
+        );
+        mol.lattice = Some(Array2::from_diag(&ndarray::arr1(&[10.0, 10.0, 10.0])));
+        let v = Geometry::new(&mol).unwrap().vector(0, 1);
+        assert!((v[0] + 0.8).abs() < 1e-4);
+    }
+}
@@ -1,28 +1,103 @@
 //#![feature(str_split_whitespace_as_str)]
 
+use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
 use std::mem;
 use std::path::PathBuf;
 use std::result::Result;
 
-use ml::predict_mol;
+use elements::{parse_element, Element};
+use environment::{environment_names, Environment};
+use geometry::Geometry;
+#[cfg(not(target_arch = "wasm32"))]
+use ml::{predict_mol, predict_with_config};
+#[cfg(not(target_arch = "wasm32"))]
+use xgboost::Booster;
 use ndarray::{ arr2, indices_of, Array, Array2};
 
 use polars::prelude::*;
+use serde::{Deserialize, Serialize};
 
+pub mod benchmark;
+pub mod breakdown;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod crossval;
+pub mod dataset;
+pub mod elements;
+pub mod environment;
+pub mod fileio;
+pub mod geometry;
+pub mod metadata;
+pub mod metrics;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod ml;
+pub mod pbc;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod qm;
+pub mod radii;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod search;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod server;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod trajectory;
+pub mod trees;
+pub mod units;
 mod utils;
+pub mod validate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod valence;
 
-use utils::{argsort, distance_matrix, transpose};
+use utils::{argsort, distance_matrix, l2_dist, transpose};
+use valence::postprocess;
 
 /// float type can be change
 pub type Float = f32;
-/// distance cut off
+/// distance cut off of the default model
 const DIST_CUTOFF: Float = 3.0;
-/// number of neighbors
+/// number of neighbors of the default model
 const N_CUT: usize = 3;
 
+/// Parameters of the featurisation, a model has to be used with those it was trained with
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FeatureConfig {
+    /// pairs further apart are not in the feature table
+    pub dist_cutoff: Float,
+    /// nearest neighbors of each atom of a pair
+    pub n_cut: usize,
+    /// adds angles, pyramidalisation and torsion of the pair (see `geometry`)
+    #[serde(default)]
+    pub angles: bool,
+    /// adds coordination numbers, heavy atom and hydrogen counts of both atoms (see `environment`)
+    #[serde(default)]
+    pub environment: bool,
+    /// adds radial distribution functions of both atoms
+    #[serde(default)]
+    pub radial: bool,
+    /// adds the spin multiplicity of the molecule as last column
+    #[serde(default)]
+    pub multiplicity: bool,
+}
+
+impl Default for FeatureConfig {
+    fn default() -> Self {
+        FeatureConfig {
+            dist_cutoff: DIST_CUTOFF,
+            n_cut: N_CUT,
+            angles: false,
+            environment: false,
+            radial: false,
+            multiplicity: false,
+        }
+    }
+}
+
+/// element order of the feature table the model was trained with
 static ELEMENTS: &[&str] = &[
     "H", "He", "Li", "Be", "B", "C", "N", "O", "F", "Ne", "Na", "Mg", "Al", "Si", "P", "S", "Cl",
     "Ar", "K", "Ca", "Sc", "Ti", "V", "Cr", "Mn", "Fe", "Co", "Ni", "Cu", "Zn", "Ga", "Ge", "As",
@@ -34,58 +109,192 @@ static ELEMENTS: &[&str] = &[
 ];
 
 /// Simple Molecule structure
-#[derive(Default)]
+#[derive(Default, Serialize)]
 pub struct XYZMolecule {
     pub natoms: usize,
+    /// element symbols, normalized if they could be parsed
     pub atoms: Vec<String>,
+    /// parsed elements, `None` for unknown symbols
+    pub elements: Vec<Option<Element>>,
     pub coords: Array2<Float>,
     pub q: i32,
+    /// spin multiplicity 2S+1
+    pub multiplicity: u32,
     pub info: String,
     pub name: String,
+    /// unpaired electrons per atom, set by the valence post-processing
+    pub radicals: Vec<u32>,
+    /// formal charges per atom, set by the valence post-processing
+    pub charges: Vec<i32>,
+    /// periodic cell vectors as rows, distances use the minimum image if set
+    pub lattice: Option<Array2<Float>>,
+    /// coordinates are known to be in Angstrom (quantum chemistry outputs), `--units` leaves them
+    pub angstrom: bool,
+}
+
+/// Atom of a perceived molecule for serialization
+#[derive(Debug, Serialize)]
+pub struct PerceivedAtom {
+    pub element: String,
+    pub x: Float,
+    pub y: Float,
+    pub z: Float,
+    pub formal_charge: i32,
+    pub radical: u32,
+}
+
+/// Bond of a perceived molecule, atom indices are zero based
+#[derive(Debug, Serialize)]
+pub struct PerceivedBond {
+    pub atom1: usize,
+    pub atom2: usize,
+    pub order: u32,
+    /// model probability of the predicted bond order
+    pub probability: Float,
+    pub distance: Float,
+}
+
+/// Result of the bond perception, e.g. for JSON output
+#[derive(Debug, Serialize)]
+pub struct PerceivedMolecule {
+    pub name: String,
+    pub info: String,
+    /// key-value pairs of an extended xyz comment line
+    pub metadata: HashMap<String, String>,
+    pub charge: i32,
+    pub multiplicity: u32,
+    pub atoms: Vec<PerceivedAtom>,
+    pub bonds: Vec<PerceivedBond>,
 }
 
 /// Implementation of Molecule structure
 impl XYZMolecule {
     /// creating a molecule from its core features
+    ///
+    /// Symbols are normalized (e.g. `CL`, `Cl1` or `17` become `Cl`), unknown symbols are kept.
     pub fn new(atoms: Vec<String>, coords: Array2<Float>, q: i32) -> Self {
         let natoms = atoms.len();
         assert_eq!(atoms.len(), coords.len() / 3);
+        let elements: Vec<Option<Element>> = atoms.iter().map(|a| parse_element(a).ok()).collect();
+        let atoms = atoms
+            .into_iter()
+            .zip(elements.iter())
+            .map(|(a, e)| e.map_or(a, |e| e.symbol().to_owned()))
+            .collect();
         XYZMolecule {
             natoms,
             atoms,
+            elements,
             coords,
             q,
+            multiplicity: 1,
             ..Default::default()
         }
     }
+
+    /// Periodic cell of the lattice, `None` for molecules without one
+    pub fn cell(&self) -> Result<Option<pbc::Cell>, Box<dyn Error>> {
+        match &self.lattice {
+            Some(lattice) => Ok(Some(pbc::Cell::new(lattice)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Distance matrix, with minimum image distances for periodic molecules
+    pub fn distance_matrix(&self) -> Result<Array2<Float>, Box<dyn Error>> {
+        match self.cell()? {
+            Some(cell) => Ok(cell.distance_matrix(&self.coords)),
+            None => Ok(distance_matrix(&self.coords)),
+        }
+    }
 }
 
 pub fn mol_from_xyz_file(filename: &str) -> Result<XYZMolecule, Box<dyn Error>> {
-    let contents = fs::read_to_string(filename)?;
+    let contents = fileio::read_to_string(filename)?;
     mol_from_xyz_string(&contents)
 }
 
+/// Parses all frames of a multi-frame xyz file, e.g. a trajectory or conformer ensemble
+pub fn mols_from_xyz_string(contents: &str) -> Result<Vec<XYZMolecule>, Box<dyn Error>> {
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut mols = Vec::<XYZMolecule>::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if lines[i].trim().is_empty() {
+            i += 1;
+            continue;
+        }
+        let natoms: usize = lines[i].trim().parse()?;
+        let end = i + natoms + 2;
+        if end > lines.len() {
+            return Err(format!("Incomplete xyz frame starting at line {}", i + 1).into());
+        }
+        mols.push(parse_xyz_contents(&lines[i..end].join("\n"))?);
+        i = end;
+    }
+    Ok(mols)
+}
+
+/// All frames of a (possibly compressed) multi-frame xyz file
+pub fn mols_from_xyz_file(filename: &str) -> Result<Vec<XYZMolecule>, Box<dyn Error>> {
+    let contents = fileio::read_to_string(filename)?;
+    mols_from_xyz_string(&contents)
+}
+
+/// Reads a molecule from an xyz file or a quantum chemistry output (Gaussian, ORCA, xtb),
+/// gzip or zstd compressed files are recognized by their extension
+pub fn mol_from_file(filename: &str) -> Result<XYZMolecule, Box<dyn Error>> {
+    let contents = fileio::read_to_string(filename)?;
+    mol_from_string(&contents)
+}
+
+/// Parses xyz contents or, if the first line is no atom count, a quantum chemistry output
+pub fn mol_from_string(contents: &str) -> Result<XYZMolecule, Box<dyn Error>> {
+    let first = contents.lines().next().unwrap_or_default();
+    if first.trim().parse::<usize>().is_ok() {
+        mol_from_xyz_string(contents)
+    } else {
+        qm::mol_from_qm_string(contents)
+    }
+}
+
 pub fn mol_from_xyz_string(contents: &str) -> Result<XYZMolecule, Box<dyn Error>> {
     let mol = parse_xyz_contents(&contents)?;
     Ok(mol)
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 pub fn molblock_from_xyz_string(contents: &str) -> Result<String, Box<dyn Error>> {
-    let mol = parse_xyz_contents(&contents)?;
-    let df = predict_mol(&mol);
+    let mut mol = parse_xyz_contents(&contents)?;
+    let df = predict_mol(&mol)?;
+    let df = postprocess(&mut mol, df)?;
+    let molblock = create_molblock(mol, df)?;
+    Ok(molblock)
+}
+
+/// Same as `molblock_from_xyz_string` with an already loaded model and without file access
+#[cfg(not(target_arch = "wasm32"))]
+pub fn molblock_from_xyz_string_with_model(
+    contents: &str,
+    booster: &Booster,
+    config: &FeatureConfig,
+) -> Result<String, Box<dyn Error>> {
+    let mut mol = parse_xyz_contents(&contents)?;
+    let df = predict_with_config(booster, &mol, config)?;
+    let df = postprocess(&mut mol, df)?;
     let molblock = create_molblock(mol, df)?;
     Ok(molblock)
 }
 
 
 
-/// Returns all files in directory with extension
+/// Returns all files in directory with extension, also if compressed (e.g. `.xyz.gz`)
 pub fn scan_directory(path: &str, extension: &str) -> Vec<PathBuf> {
     let paths = fs::read_dir(path).unwrap();
     let mut path_vec = Vec::<PathBuf>::new();
     for path in paths {
         let p = path.unwrap().path();
-        if p.extension().unwrap() == extension {
+        if fileio::strip_compression(&p).extension().map_or(false, |e| e == extension) {
             path_vec.push(p);
         }
     }
@@ -111,6 +320,7 @@ fn parse_xyz_contents(contents: &str) -> Result<XYZMolecule, Box<dyn Error>> {
         if i > 1 && line.trim().len() > 0 {
             nrows += 1;
             let mut iter = line.split_whitespace();
+            // unknown symbols are kept, the geometry checks decide about them
             let atom: &str = iter.next().unwrap_or_default();
             atoms.push(atom.to_owned());
             let x: Float = iter.next().unwrap_or_default().parse()?;
@@ -120,38 +330,135 @@ fn parse_xyz_contents(contents: &str) -> Result<XYZMolecule, Box<dyn Error>> {
             coords.append(&mut coord);
         }
     }
-    assert_eq!(natoms as usize, nrows);
-    let coords = Array2::from_shape_vec((nrows, 3), coords).unwrap();
+    if natoms != nrows {
+        return Err(format!("Expected {} atoms, found {} atom lines", natoms, nrows).into());
+    }
+    let coords = Array2::from_shape_vec((nrows, 3), coords)?;
     let mut molecule = XYZMolecule::new(atoms, coords, 0);
+    let props = parse_extxyz_info(info);
+    if let Some(q) = props.get("charge") {
+        molecule.q = q.parse()?;
+    }
+    if let Some(mult) = props.get("multiplicity").or_else(|| props.get("mult")) {
+        molecule.multiplicity = mult.parse()?;
+    }
+    if let Some(lattice) = props.get("lattice") {
+        molecule.lattice = Some(pbc::parse_lattice(lattice)?);
+    }
     molecule.info = info.to_owned();
     Ok(molecule)
 }
 
+/// Key-value pairs of an extended xyz comment line, e.g. `charge=-1 multiplicity=2 Lattice="..."`
+///
+/// Keys are lower cased, quotes around values are removed.
+/// Plain comment lines without `=` give an empty map.
+pub fn parse_extxyz_info(info: &str) -> HashMap<String, String> {
+    let mut tokens = Vec::<String>::new();
+    let mut token = String::new();
+    let mut quoted = false;
+    for c in info.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !token.is_empty() {
+                    tokens.push(mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    let mut props = HashMap::new();
+    for t in tokens {
+        if let Some((key, value)) = t.split_once('=') {
+            props.insert(key.to_lowercase(), value.to_owned());
+        }
+    }
+    props
+}
+
+/// Index of an element in the feature table, elements unknown to the model are an error
+pub(crate) fn element_index(symbol: &str) -> Result<usize, Box<dyn Error>> {
+    ELEMENTS
+        .iter()
+        .position(|&s| s == symbol)
+        .ok_or_else(|| format!("Element {} is not supported by the model", symbol).into())
+}
+
+/// Column names of the feature table as created by `create_dataframe`
+pub fn feature_names(config: &FeatureConfig) -> Vec<String> {
+    let mut names: Vec<String> = ["id1", "id2", "q", "ata", "atb", "distab"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    for (label, label2) in [("a", "b"), ("b", "a")] {
+        for k in 1..=config.n_cut {
+            names.push(format!("at{}{}", label, k));
+            names.push(format!("dist{}{}", label, k));
+            names.push(format!("dist{}{}{}", label, k, label2));
+        }
+    }
+    if config.angles {
+        for label in ["a", "b"] {
+            names.extend((1..=config.n_cut).map(|k| format!("ang{}{}", label, k)));
+        }
+        names.extend(["pyra", "pyrb", "torsab"].iter().map(|s| s.to_string()));
+    }
+    names.extend(environment_names(config));
+    if config.multiplicity {
+        names.push("mult".to_owned());
+    }
+    names
+}
+
 /// Create a 2D ndarray with local bond information from distance matrix
 /// https://docs.rs/ndarray/latest/ndarray/doc/ndarray_for_numpy_users/index.html#similarities
-pub fn create_dataframe(mol: &XYZMolecule) -> Result<DataFrame, Box<dyn Error>> {
-    let dm = distance_matrix(&mol.coords);
+pub fn create_dataframe(
+    mol: &XYZMolecule,
+    config: &FeatureConfig,
+) -> Result<DataFrame, Box<dyn Error>> {
+    if let Some(cell) = mol.cell()? {
+        if cell.width() < 2.0 * config.dist_cutoff {
+            return Err(format!(
+                "Cell is {:.3} A wide, less than twice the distance cut off of {} A",
+                cell.width(),
+                config.dist_cutoff
+            )
+            .into());
+        }
+    }
+    let dm = mol.distance_matrix()?;
     assert_eq!(mol.natoms, dm.ncols());
     let mut header = Vec::<String>::new();
     let mut features = Vec::<Vec<Float>>::new();
+    let geometry = if config.angles {
+        Some(Geometry::new(mol)?)
+    } else {
+        None
+    };
+    let environment = if config.environment || config.radial {
+        Some(Environment::new(mol, &dm, config)?)
+    } else {
+        None
+    };
     //println!("first:{:?}",features);
     //iterate over rows of distance matrix
-    println!();
     for i in 0..dm.ncols() {
         for j in 0..dm.ncols() {
             if i >= j {
                 continue;
             }
             let dist = dm[[i, j]];
-            if dist > DIST_CUTOFF {
+            if dist > config.dist_cutoff {
                 continue;
             }
             let mut i_tmp = i;
             let mut j_tmp = j;
-            let e1 = &mol.atoms[i_tmp];
-            let e2 = &mol.atoms[j_tmp];
-            let mut an1 = ELEMENTS.iter().position(|&s| s == e1).unwrap_or_default();
-            let mut an2 = ELEMENTS.iter().position(|&s| s == e2).unwrap_or_default();
+            let mut an1 = element_index(&mol.atoms[i_tmp])?;
+            let mut an2 = element_index(&mol.atoms[j_tmp])?;
             if an1 < an2 {
                 mem::swap(&mut i_tmp, &mut j_tmp);
                 mem::swap(&mut an1, &mut an2);
@@ -175,43 +482,76 @@ pub fn create_dataframe(mol: &XYZMolecule) -> Result<DataFrame, Box<dyn Error>>
                 ]);
             }
             //now go over neighbors of i an j
+            let mut neighbors = [Vec::<usize>::new(), Vec::<usize>::new()];
             for a in [i_tmp, j_tmp] {
                 let b = if a == i_tmp { j_tmp } else { i_tmp };
                 let label = if a == i_tmp { "a" } else { "b" };
                 let label2 = if a == i_tmp { "b" } else { "a" };
                 let row = dm.row(a).to_vec();
-                let row_sorted = argsort(&row);
-                let mut k = 0;
-                for nextn in row_sorted.into_iter() {
-                    if nextn == j_tmp || nextn == i_tmp {
-                        continue;
+                let nearest: Vec<usize> = argsort(&row)
+                    .into_iter()
+                    .filter(|n| *n != i_tmp && *n != j_tmp)
+                    .take(config.n_cut)
+                    .collect();
+                // neighbors missing in small molecules are zeros (missing values of the
+                // model), so that every row has the columns of `feature_names`
+                for k in 0..config.n_cut {
+                    match nearest.get(k) {
+                        Some(&nextn) => {
+                            let dist = dm[[a, nextn]];
+                            let an_next = element_index(&mol.atoms[nextn])?;
+                            let distb = dm[[b, nextn]];
+
+                            data_row.push(an_next as Float);
+                            data_row.push(dist);
+                            data_row.push(distb);
+                        }
+                        None => data_row.extend([0.0; 3]),
                     }
-                    if k >= N_CUT {
-                        break;
-                    };
-                    let dist = dm[[a, nextn]];
-                    let el_next = &mol.atoms[nextn];
-                    let an_next = ELEMENTS
-                        .iter()
-                        .position(|&s| s == el_next)
-                        .unwrap_or_default();
-                    let distb = dm[[b, nextn]];
-
-                    data_row.push(an_next as Float);
-                    data_row.push(dist);
-                    data_row.push(distb);
                     if features.len() == 0 {
                         let astr: String = format!("{}{}{}", "at", label, k + 1);
                         let bstr: String = format!("{}{}{}", "dist", label, k + 1);
                         let cstr: String = format!("{}{}{}{}", "dist", label, k + 1, label2);
                         header.append(&mut vec![astr, bstr, cstr]);
                     }
-                    k += 1;
+                }
+                neighbors[if a == i_tmp { 0 } else { 1 }] = nearest;
+            }
+            if let Some(geometry) = &geometry {
+                let [neighbors_a, neighbors_b] = &neighbors;
+                for (name, value) in
+                    geometry.pair_features(i_tmp, j_tmp, neighbors_a, neighbors_b, config.n_cut)
+                {
+                    data_row.push(value);
+                    if features.len() == 0 {
+                        header.push(name);
+                    }
+                }
+            }
+            if let Some(environment) = &environment {
+                for (name, value) in environment.pair_features(i_tmp, j_tmp, config) {
+                    data_row.push(value);
+                    if features.len() == 0 {
+                        header.push(name);
+                    }
+                }
+            }
+            if config.multiplicity {
+                data_row.push(mol.multiplicity as Float);
+                if features.len() == 0 {
+                    header.push("mult".to_string());
                 }
             }
             features.push(data_row);
         }
     }
+    if features.is_empty() {
+        return Err(format!(
+            "No atom pairs within {} Angstrom, nothing to predict",
+            config.dist_cutoff
+        )
+        .into());
+    }
     let features_col = transpose(features);
     let mut series = Vec::<Series>::new();
     assert_eq!(features_col.len(), header.len());
@@ -220,7 +560,7 @@ pub fn create_dataframe(mol: &XYZMolecule) -> Result<DataFrame, Box<dyn Error>>
         let s = Series::new(name, col.to_owned());
         series.push(s);
     }
-    let df = DataFrame::new(series).unwrap();
+    let df = DataFrame::new(series)?;
     Ok(df)
 }
 
@@ -231,13 +571,34 @@ pub fn get_bonds(df: &DataFrame) -> u32 {
     nbonds
 }
 
+/// Atom pairs (zero based) with their predicted bond order for every row of the dataframe
+pub fn bond_orders(df: &DataFrame) -> Result<Vec<(usize, usize, u32)>, Box<dyn Error>> {
+    let id1 = df.column("id1")?.f32()?;
+    let id2 = df.column("id2")?.f32()?;
+    let preds = df.column("preds")?.f32()?;
+    let rows = id1
+        .into_iter()
+        .zip(id2.into_iter())
+        .zip(preds.into_iter())
+        .map(|((a, b), p)| {
+            (
+                a.unwrap_or(1.0) as usize - 1,
+                b.unwrap_or(1.0) as usize - 1,
+                p.unwrap_or_default() as u32,
+            )
+        })
+        .collect();
+    Ok(rows)
+}
+
 pub fn create_molblock(mol: XYZMolecule, df: DataFrame) -> Result<String, Box<dyn Error>> {
-    let bonds = df.column("preds")?;
-    let mask = bonds.gt(0);
-    let df = df.filter(&mask)?;
+    let bonds: Vec<(usize, usize, u32)> = bond_orders(&df)?
+        .into_iter()
+        .filter(|(_, _, order)| *order > 0)
+        .collect();
 
     let natoms = mol.atoms.len();
-    let nbonds = mask.sum().unwrap();
+    let nbonds = bonds.len();
 
     let mut ins: String = mol.name + "\n";
 
@@ -255,18 +616,96 @@ pub fn create_molblock(mol: XYZMolecule, df: DataFrame) -> Result<String, Box<dy
     }
 
     // bond block
-    let (nrows, ncols) = df.shape();
+    for (id1, id2, bond) in bonds {
+        ins += format!("{:>3}{:>3}{:>3} 0  0  0  0  0\n", id1 + 1, id2 + 1, bond).as_str();
+    }
 
-    for i in 0..nrows {
-        let row = df.get(i).unwrap();
-        let id1 = &row[0].to_string();
-        let id2 = &row[1].to_string();
-        let bond = &row[ncols - 1].to_string();
-        ins += format!("{:>3}{:>3}{:>3} 0  0  0  0  0\n", id1, id2, bond).as_str();
+    // properties block, radicals as spin multiplicity of the atom
+    let radicals: Vec<(usize, u32)> = mol
+        .radicals
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| **r > 0)
+        .map(|(i, r)| (i + 1, r + 1))
+        .collect();
+    for chunk in radicals.chunks(8) {
+        ins += format!("M  RAD{:>3}", chunk.len()).as_str();
+        for (idx, rad) in chunk {
+            ins += format!(" {:>3} {:>3}", idx, rad).as_str();
+        }
+        ins += "\n";
     }
+    let charges: Vec<(usize, i32)> = mol
+        .charges
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| **c != 0)
+        .map(|(i, c)| (i + 1, *c))
+        .collect();
+    for chunk in charges.chunks(8) {
+        ins += format!("M  CHG{:>3}", chunk.len()).as_str();
+        for (idx, chg) in chunk {
+            ins += format!(" {:>3} {:>3}", idx, chg).as_str();
+        }
+        ins += "\n";
+    }
+    ins += "M  END\n";
     Ok(ins)
 }
 
+/// Collects atoms and perceived bonds with their probabilities
+pub fn perceived_molecule(
+    mol: &XYZMolecule,
+    df: &DataFrame,
+) -> Result<PerceivedMolecule, Box<dyn Error>> {
+    let probs: Vec<Float> = match df.column("prob") {
+        Ok(prob) => prob.f32()?.into_iter().map(|p| p.unwrap_or(1.0)).collect(),
+        Err(_) => vec![1.0; df.height()],
+    };
+    let cell = mol.cell()?;
+    let mut bonds = Vec::<PerceivedBond>::new();
+    for ((i, j, order), probability) in bond_orders(df)?.into_iter().zip(probs) {
+        if order == 0 {
+            continue;
+        }
+        bonds.push(PerceivedBond {
+            atom1: i.min(j),
+            atom2: i.max(j),
+            order,
+            probability,
+            distance: match &cell {
+                Some(cell) => cell.distance(&mol.coords.row(i), &mol.coords.row(j)),
+                None => l2_dist(&mol.coords.row(i), &mol.coords.row(j)),
+            },
+        });
+    }
+    let atoms = (0..mol.natoms)
+        .map(|i| PerceivedAtom {
+            element: mol.atoms[i].clone(),
+            x: mol.coords[[i, 0]],
+            y: mol.coords[[i, 1]],
+            z: mol.coords[[i, 2]],
+            formal_charge: mol.charges.get(i).cloned().unwrap_or_default(),
+            radical: mol.radicals.get(i).cloned().unwrap_or_default(),
+        })
+        .collect();
+    Ok(PerceivedMolecule {
+        name: mol.name.clone(),
+        info: mol.info.clone(),
+        metadata: parse_extxyz_info(&mol.info),
+        charge: mol.q,
+        multiplicity: mol.multiplicity,
+        atoms,
+        bonds,
+    })
+}
+
+/// JSON representation of the perceived molecule
+pub fn create_json(mol: &XYZMolecule, df: &DataFrame) -> Result<String, Box<dyn Error>> {
+    let perceived = perceived_molecule(mol, df)?;
+    Ok(serde_json::to_string_pretty(&perceived)?)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::ml::predict_mol;
@@ -289,10 +728,113 @@ mod tests {
         let mol = mol_from_xyz_string(mol_str).expect("Failed parsing!");
         assert_eq!(mol.coords.len(), 6);
         assert_eq!(mol.atoms.len(), 2);
-        let df = predict_mol(&mol);
+        let df = predict_mol(&mol).unwrap();
         println!("{}",df);
         let molblock = create_molblock(mol,df).expect("Failed molblock!");
-        assert_eq!(molblock.len(), 176);
+        assert_eq!(molblock.len(), 183);
+    }
+    #[test]
+    fn invalid_inputs_are_errors() {
+        assert!(mol_from_xyz_string("3\n\nC 0 0 0").is_err());
+        let single = mol_from_xyz_string("1\n\nC 0 0 0").unwrap();
+        assert!(create_dataframe(&single, &FeatureConfig::default()).is_err());
+        let apart = mol_from_xyz_string("2\n\nC 0 0 0\nC 10 0 0").unwrap();
+        assert!(create_dataframe(&apart, &FeatureConfig::default()).is_err());
+        assert!(molblock_from_xyz_string("1\n\nC 0 0 0").is_err());
+        assert!(molblock_from_xyz_string("2\n\nXx 0 0 0\nC 1.5 0 0").is_err());
+    }
+    #[test]
+    fn parse_extxyz() {
+        let mol_str = "2
+charge=-1 multiplicity=2 comment=\"two words\"
+        O          0.00000        0.00000        0.00000
+        H          0.00000        0.00000        0.97000";
+        let mol = mol_from_xyz_string(mol_str).expect("Failed parsing!");
+        assert_eq!(mol.q, -1);
+        assert_eq!(mol.multiplicity, 2);
+        let props = parse_extxyz_info(&mol.info);
+        assert_eq!(props["comment"], "two words");
+        assert!(parse_extxyz_info("100005").is_empty());
+    }
+    #[test]
+    fn parse_element_labels() {
+        let mol_str = "3
+
+        8          0.00000        0.00000        0.00000
+        H1         0.00000        0.00000        0.97000
+        h2         0.93000        0.00000       -0.24000";
+        let mol = mol_from_xyz_string(mol_str).expect("Failed parsing!");
+        assert_eq!(mol.atoms, vec!["O", "H", "H"]);
+        assert_eq!(mol.elements[0].unwrap().atomic_number(), 8);
+        let mol = mol_from_xyz_string("1\n\nXx 0.0 0.0 0.0").expect("Failed parsing!");
+        assert_eq!(mol.atoms, vec!["Xx"]);
+        assert!(mol.elements[0].is_none());
+        let issues = validate::check_molecule(&mol, 0.5).unwrap();
+        assert_eq!(issues[0].check, validate::Check::UnknownElement);
+        let atoms = vec!["CL".to_string(), "Xx".to_string()];
+        let mol = XYZMolecule::new(atoms, Array2::zeros((2, 3)), 0);
+        assert_eq!(mol.atoms, vec!["Cl", "Xx"]);
+        assert!(mol.elements[1].is_none());
+        assert!(create_dataframe(&mol, &FeatureConfig::default()).is_err());
+    }
+    #[test]
+    fn periodic_dataframe() {
+        let mol_str = "2
+Lattice=\"10.0 0.0 0.0 0.0 10.0 0.0 0.0 0.0 10.0\"
+        O          0.20000        5.00000        5.00000
+        H          9.40000        5.00000        5.00000";
+        let mol = mol_from_xyz_string(mol_str).expect("Failed parsing!");
+        assert!(mol.lattice.is_some());
+        let df = create_dataframe(&mol, &FeatureConfig::default()).unwrap();
+        let dist = df.column("distab").unwrap().f32().unwrap().get(0).unwrap();
+        assert!((dist - 0.8).abs() < 1e-4);
+    }
+    #[test]
+    fn molblock_radicals() {
+        let atoms: Vec<String> = vec!["O".to_string(), "O".to_string()];
+        let coords: Array2<Float> = arr2(&[[0.0, 0.0, 0.0], [0.0, 0.0, 1.21]]);
+        let mut mol = XYZMolecule::new(atoms, coords, 0);
+        mol.radicals = vec![1, 1];
+        let df = DataFrame::new(vec![
+            Series::new("id1", vec![1.0 as Float]),
+            Series::new("id2", vec![2.0 as Float]),
+            Series::new("preds", vec![1.0 as Float]),
+        ])
+        .unwrap();
+        let molblock = create_molblock(mol, df).expect("Failed molblock!");
+        assert!(molblock.contains("  1  2  1 0  0  0  0  0\n"));
+        assert!(molblock.ends_with("M  RAD  2   1   2   2   2\nM  END\n"));
+    }
+    #[test]
+    fn json_output() {
+        let atoms: Vec<String> = vec!["O".to_string(), "H".to_string(), "H".to_string()];
+        let coords: Array2<Float> = arr2(&[[0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]]);
+        let mol = XYZMolecule::new(atoms, coords, 0);
+        let df = DataFrame::new(vec![
+            Series::new("id1", vec![1.0 as Float, 1.0, 3.0]),
+            Series::new("id2", vec![2.0 as Float, 3.0, 2.0]),
+            Series::new("preds", vec![1.0 as Float, 1.0, 0.0]),
+            Series::new("prob", vec![0.9 as Float, 0.8, 0.7]),
+        ])
+        .unwrap();
+        let perceived = perceived_molecule(&mol, &df).unwrap();
+        assert_eq!(perceived.atoms.len(), 3);
+        assert_eq!(perceived.bonds.len(), 2);
+        assert_eq!(perceived.bonds[1].atom2, 2);
+        assert!((perceived.bonds[0].probability - 0.9).abs() < 1e-6);
+        let json = create_json(&mol, &df).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["bonds"][0]["order"], 1);
+        assert_eq!(value["atoms"][0]["element"], "O");
+    }
+    #[test]
+    fn parse_frames() {
+        let contents = fs::read_to_string("data/test1.xyz").unwrap()
+            + fs::read_to_string("data/test2.xyz").unwrap().as_str();
+        let mols = mols_from_xyz_string(&contents).expect("Failed parsing!");
+        assert_eq!(mols.len(), 2);
+        assert_eq!(mols[0].natoms, 23);
+        assert!(mols_from_xyz_string("3\n\nC 0 0 0\n").is_err());
     }
     #[test]
     fn parse_xyz() {
@@ -310,7 +852,7 @@ mod tests {
                 let fname = path.to_str().unwrap();
                 let mol = mol_from_xyz_file(fname).expect("Could not read file!");
                 distance_matrix(&mol.coords);
-                let df = create_dataframe(&mol);
+                let df = create_dataframe(&mol, &FeatureConfig::default());
                 assert!(df.is_ok());
             }
         }
@@ -318,10 +860,71 @@ mod tests {
     #[test]
     fn test_df() {
         let mol = mol_from_xyz_file("data/test1.xyz").expect("Could not open file!");
-        let df = create_dataframe(&mol).unwrap();
+        let df = create_dataframe(&mol, &FeatureConfig::default()).unwrap();
         println!("df.shape:{:?}", df.shape());
         assert_eq!(df.shape().0, 90);
         assert_eq!(df.shape().1, 24);
+        assert_eq!(
+            df.get_column_names(),
+            feature_names(&FeatureConfig::default())
+        );
+        let config = FeatureConfig {
+            dist_cutoff: 4.0,
+            n_cut: 5,
+            ..Default::default()
+        };
+        let wide = create_dataframe(&mol, &config).unwrap();
+        assert!(wide.shape().0 > 90);
+        assert_eq!(wide.shape().1, 36);
+        assert_eq!(wide.get_column_names(), feature_names(&config));
+        let config = FeatureConfig {
+            angles: true,
+            ..Default::default()
+        };
+        let angles = create_dataframe(&mol, &config).unwrap();
+        assert_eq!(angles.shape(), (90, 33));
+        assert_eq!(angles.get_column_names(), feature_names(&config));
+        let config = FeatureConfig {
+            environment: true,
+            radial: true,
+            ..Default::default()
+        };
+        let env = create_dataframe(&mol, &config).unwrap();
+        assert_eq!(env.shape(), (90, 38));
+        assert_eq!(env.get_column_names(), feature_names(&config));
+        let config = FeatureConfig {
+            multiplicity: true,
+            ..Default::default()
+        };
+        let mut triplet = mol_from_xyz_file("data/test1.xyz").unwrap();
+        triplet.multiplicity = 3;
+        let mult = create_dataframe(&triplet, &config).unwrap();
+        assert_eq!(mult.shape(), (90, 25));
+        assert_eq!(mult.get_column_names(), feature_names(&config));
+        let column = mult.column("mult").unwrap().f32().unwrap();
+        assert!(column.into_iter().all(|m| m == Some(3.0)));
+    }
+    #[test]
+    fn test_small_molecule_columns() {
+        // water has one neighbor per atom of a pair, fewer than n_cut
+        let atoms = ["O", "H", "H"].iter().map(|s| s.to_string()).collect();
+        let coords = arr2(&[[0.0, 0.0, 0.0], [0.96, 0.0, 0.0], [-0.24, 0.93, 0.0]]);
+        let mol = XYZMolecule::new(atoms, coords, 0);
+        let config = FeatureConfig {
+            angles: true,
+            environment: true,
+            radial: true,
+            multiplicity: true,
+            ..Default::default()
+        };
+        for config in [FeatureConfig::default(), config] {
+            let df = create_dataframe(&mol, &config).unwrap();
+            assert_eq!(df.width(), feature_names(&config).len());
+            assert_eq!(df.get_column_names(), feature_names(&config));
+            // the missing second and third neighbors are zeros, not the next features
+            let ata3 = df.column("ata3").unwrap().f32().unwrap();
+            assert!(ata3.into_iter().all(|v| v == Some(0.0)));
+        }
     }
     #[test]
     fn test_scandir() {
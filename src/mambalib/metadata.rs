@@ -0,0 +1,244 @@
+//! Metadata stored next to a model file.
+//!
+//! A booster file does not record the feature layout it was trained with, a
+//! model trained with another `FeatureConfig` or before a change of the column
+//! order of `create_dataframe` would silently predict garbage. The metadata is
+//! written as JSON to `<model>.json` together with the provenance of the model
+//! (training data hash, parameters, metrics, crate version) and checked when a
+//! model is loaded. `featurize` writes the same file next to a data set, so that
+//! training uses the featurisation the data was made with.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::metrics::BOND_CLASSES;
+use crate::{feature_names, FeatureConfig, Float, ELEMENTS};
+
+/// Hyperparameters of the tree booster
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrainParams {
+    pub max_depth: u32,
+    pub eta: f32,
+    /// fraction of the rows sampled for each tree
+    pub subsample: f32,
+    /// fraction of the features sampled for each tree
+    pub colsample_bytree: f32,
+    pub min_child_weight: f32,
+    /// number of boosting rounds
+    pub rounds: u32,
+}
+
+impl Default for TrainParams {
+    fn default() -> Self {
+        TrainParams {
+            max_depth: 6,
+            eta: 0.1,
+            subsample: 1.0,
+            colsample_bytree: 1.0,
+            min_child_weight: 1.0,
+            rounds: 200,
+        }
+    }
+}
+
+/// Feature layout and provenance of a model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelMetadata {
+    /// version of mamba which trained the model
+    pub crate_version: String,
+    /// columns of the feature table in order
+    pub features: Vec<String>,
+    #[serde(flatten)]
+    pub config: FeatureConfig,
+    /// element order of the `ata`/`atb` features
+    pub elements: Vec<String>,
+    /// bond class of every predicted label
+    pub classes: Vec<String>,
+    pub training_data: Option<String>,
+    /// FNV-1a hash of the training data file
+    pub training_hash: Option<String>,
+    pub params: Option<TrainParams>,
+    pub metrics: BTreeMap<String, Float>,
+}
+
+impl ModelMetadata {
+    /// Metadata of a featurisation of this build, without provenance
+    pub fn new(config: FeatureConfig) -> Self {
+        ModelMetadata {
+            crate_version: env!("CARGO_PKG_VERSION").to_owned(),
+            features: feature_names(&config),
+            config,
+            elements: ELEMENTS.iter().map(|s| s.to_string()).collect(),
+            classes: BOND_CLASSES.iter().map(|s| s.to_string()).collect(),
+            training_data: None,
+            training_hash: None,
+            params: None,
+            metrics: BTreeMap::new(),
+        }
+    }
+
+    /// Records the training data file and its hash
+    pub fn with_training_data(mut self, path: &str) -> Result<Self, Box<dyn Error>> {
+        self.training_hash = Some(format!("{:016x}", fnv1a(&fs::read(path)?)));
+        self.training_data = Some(path.to_owned());
+        Ok(self)
+    }
+
+    /// Differences of the feature layout to the featurisation of this build with the same config
+    pub fn mismatches(&self) -> Vec<String> {
+        let current = ModelMetadata::new(self.config);
+        let mut mismatches = Vec::<String>::new();
+        if self.features != current.features {
+            mismatches.push(format!(
+                "features {} instead of {}",
+                self.features.join(","),
+                current.features.join(",")
+            ));
+        }
+        if self.elements != current.elements {
+            mismatches.push("different element order".to_owned());
+        }
+        if self.classes != current.classes {
+            mismatches.push(format!(
+                "classes {} instead of {}",
+                self.classes.join(","),
+                current.classes.join(",")
+            ));
+        }
+        mismatches
+    }
+
+    /// Writes the metadata next to the model file
+    pub fn save(&self, model: &str) -> Result<(), Box<dyn Error>> {
+        let json = serde_json::to_string_pretty(self)? + "\n";
+        fs::write(metadata_path(model), json)?;
+        Ok(())
+    }
+
+    /// Metadata of a model file, `None` if it has none
+    pub fn load(model: &str) -> Result<Option<Self>, Box<dyn Error>> {
+        let path = metadata_path(model);
+        if !Path::new(&path).exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(&path)?;
+        let metadata = serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid model metadata {}: {}", path, e))?;
+        Ok(Some(metadata))
+    }
+}
+
+impl Default for ModelMetadata {
+    fn default() -> Self {
+        ModelMetadata::new(FeatureConfig::default())
+    }
+}
+
+/// File of the metadata of a model
+pub fn metadata_path(model: &str) -> String {
+    format!("{}.json", model)
+}
+
+/// Checks that a model was trained with the featurisation of this build and returns
+/// its feature config, a model without metadata is only a warning and gets the default
+pub fn check_model(model: &str) -> Result<FeatureConfig, Box<dyn Error>> {
+    match ModelMetadata::load(model)? {
+        None => {
+            eprintln!(
+                "Warning: no metadata for {}, the feature layout can not be checked",
+                model
+            );
+            Ok(FeatureConfig::default())
+        }
+        Some(metadata) => {
+            let mismatches = metadata.mismatches();
+            if !mismatches.is_empty() {
+                return Err(format!(
+                    "Model {} was trained with a different featurisation: {}",
+                    model,
+                    mismatches.join("; ")
+                )
+                .into());
+            }
+            Ok(metadata.config)
+        }
+    }
+}
+
+/// Feature config recorded next to a data set by `featurize`, `None` for data sets
+/// without metadata, data sets of a different featurisation than this build are refused
+pub fn dataset_config(dataset: &str) -> Result<Option<FeatureConfig>, Box<dyn Error>> {
+    match ModelMetadata::load(dataset)? {
+        None => Ok(None),
+        Some(metadata) => {
+            let mismatches = metadata.mismatches();
+            if !mismatches.is_empty() {
+                return Err(format!(
+                    "Data set {} was featurised differently: {}",
+                    dataset,
+                    mismatches.join("; ")
+                )
+                .into());
+            }
+            Ok(Some(metadata.config))
+        }
+    }
+}
+
+/// 64 bit FNV-1a hash, stable across platforms and compiler versions
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mismatches() {
+        let config = FeatureConfig {
+            dist_cutoff: 4.0,
+            n_cut: 5,
+            angles: true,
+            ..Default::default()
+        };
+        let metadata = ModelMetadata::new(config);
+        assert!(metadata.mismatches().is_empty());
+        let json = serde_json::to_string(&metadata).unwrap();
+        assert!(json.contains("\"n_cut\":5"));
+        assert!(json.contains("\"torsab\""));
+        let mut loaded: ModelMetadata = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, metadata);
+        loaded.config.n_cut = 2;
+        loaded.classes.pop();
+        assert_eq!(loaded.mismatches().len(), 2);
+    }
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    }
+    #[test]
+    fn test_dataset_config() {
+        let path = std::env::temp_dir().join("mamba_test_dataset.libsvm");
+        let path = path.to_str().unwrap();
+        assert_eq!(dataset_config(path).unwrap(), None);
+        let config = FeatureConfig {
+            environment: true,
+            ..Default::default()
+        };
+        ModelMetadata::new(config).save(path).unwrap();
+        assert_eq!(dataset_config(path).unwrap(), Some(config));
+        fs::remove_file(metadata_path(path)).unwrap();
+    }
+    #[test]
+    fn test_shipped_model() {
+        assert_eq!(check_model("xgb.model").unwrap(), FeatureConfig::default());
+    }
+}
@@ -0,0 +1,289 @@
+//! Classification metrics for the predicted bond classes.
+//!
+//! Non-bonded pairs dominate the feature table, so the accuracy alone hides how
+//! rare classes (triple, aromatic) are predicted. The report has the confusion
+//! matrix, precision, recall and F1 per class, their macro and micro averages
+//! and Cohen's kappa, and can be written as JSON to compare training runs.
+
+use serde::Serialize;
+
+use crate::Float;
+
+/// Names of the bond classes predicted by the model, class 0 is no bond
+pub static BOND_CLASSES: &[&str] = &["none", "single", "double", "triple", "aromatic"];
+
+/// Confusion matrix with the true classes as rows and the predicted classes as columns
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfusionMatrix {
+    pub counts: Vec<Vec<usize>>,
+}
+
+impl ConfusionMatrix {
+    pub fn new(nclass: usize) -> Self {
+        ConfusionMatrix {
+            counts: vec![vec![0; nclass]; nclass],
+        }
+    }
+
+    /// Confusion matrix of labels and predictions as given by xgboost
+    pub fn from_labels(labels: &[f32], preds: &[f32], nclass: usize) -> Self {
+        let mut cm = ConfusionMatrix::new(nclass);
+        for (label, pred) in labels.iter().zip(preds) {
+            cm.add(*label as usize, *pred as usize);
+        }
+        cm
+    }
+
+    pub fn nclass(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Adds a sample, classes beyond the matrix are counted in the last class
+    pub fn add(&mut self, truth: usize, pred: usize) {
+        let last = self.nclass() - 1;
+        self.counts[truth.min(last)][pred.min(last)] += 1;
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().flatten().sum()
+    }
+
+    pub fn correct(&self) -> usize {
+        (0..self.nclass()).map(|c| self.counts[c][c]).sum()
+    }
+
+    pub fn accuracy(&self) -> Option<Float> {
+        ratio(self.correct(), self.total())
+    }
+
+    /// Fraction of the predictions of a class which are correct
+    pub fn precision(&self, class: usize) -> Option<Float> {
+        let predicted: usize = self.counts.iter().map(|row| row[class]).sum();
+        ratio(self.counts[class][class], predicted)
+    }
+
+    /// Fraction of the samples of a class which are found
+    pub fn recall(&self, class: usize) -> Option<Float> {
+        ratio(self.counts[class][class], self.counts[class].iter().sum())
+    }
+
+    /// Harmonic mean of precision and recall
+    pub fn f1(&self, class: usize) -> Option<Float> {
+        match (self.precision(class), self.recall(class)) {
+            (Some(p), Some(r)) if p + r > 0.0 => Some(2.0 * p * r / (p + r)),
+            (Some(_), Some(_)) => Some(0.0),
+            _ => None,
+        }
+    }
+
+    /// Number of samples of a class
+    pub fn support(&self, class: usize) -> usize {
+        self.counts[class].iter().sum()
+    }
+
+    /// Cohen's kappa, the agreement corrected for agreement by chance
+    pub fn kappa(&self) -> Option<Float> {
+        let total = self.total() as Float;
+        if total == 0.0 {
+            return None;
+        }
+        let observed = self.correct() as Float / total;
+        let expected: Float = (0..self.nclass())
+            .map(|c| {
+                let predicted: usize = self.counts.iter().map(|row| row[c]).sum();
+                self.support(c) as Float * predicted as Float / (total * total)
+            })
+            .sum();
+        if expected >= 1.0 {
+            return None;
+        }
+        Some((observed - expected) / (1.0 - expected))
+    }
+
+    /// Matrix as text table with the class names as labels
+    pub fn table(&self, labels: &[&str]) -> String {
+        let mut table = format!("{:>10}", "true\\pred");
+        for label in labels.iter().take(self.nclass()) {
+            table += format!("{:>10}", label).as_str();
+        }
+        table += "\n";
+        for (label, row) in labels.iter().zip(self.counts.iter()) {
+            table += format!("{:>10}", label).as_str();
+            for count in row {
+                table += format!("{:>10}", count).as_str();
+            }
+            table += "\n";
+        }
+        table
+    }
+}
+
+/// Metrics of a single class, `None` if undefined (e.g. class never predicted)
+#[derive(Debug, Serialize)]
+pub struct ClassMetrics {
+    pub label: String,
+    pub support: usize,
+    pub precision: Option<Float>,
+    pub recall: Option<Float>,
+    pub f1: Option<Float>,
+}
+
+/// All metrics of a classification, e.g. to be tracked across retraining runs
+#[derive(Debug, Serialize)]
+pub struct ClassificationReport {
+    pub samples: usize,
+    pub accuracy: Option<Float>,
+    pub kappa: Option<Float>,
+    /// unweighted mean over the classes with a defined metric
+    pub macro_precision: Option<Float>,
+    pub macro_recall: Option<Float>,
+    pub macro_f1: Option<Float>,
+    /// from the summed counts of all classes, equal to the accuracy for single label data
+    pub micro_precision: Option<Float>,
+    pub micro_recall: Option<Float>,
+    pub micro_f1: Option<Float>,
+    pub classes: Vec<ClassMetrics>,
+    /// true classes as rows, predicted classes as columns
+    pub confusion: Vec<Vec<usize>>,
+}
+
+impl ClassificationReport {
+    pub fn new(cm: &ConfusionMatrix, labels: &[&str]) -> Self {
+        let classes: Vec<ClassMetrics> = (0..cm.nclass())
+            .map(|c| ClassMetrics {
+                label: labels.get(c).map_or(c.to_string(), |l| l.to_string()),
+                support: cm.support(c),
+                precision: cm.precision(c),
+                recall: cm.recall(c),
+                f1: cm.f1(c),
+            })
+            .collect();
+        let micro = cm.accuracy();
+        ClassificationReport {
+            samples: cm.total(),
+            accuracy: cm.accuracy(),
+            kappa: cm.kappa(),
+            macro_precision: mean(classes.iter().map(|c| c.precision)),
+            macro_recall: mean(classes.iter().map(|c| c.recall)),
+            macro_f1: mean(classes.iter().map(|c| c.f1)),
+            micro_precision: micro,
+            micro_recall: micro,
+            micro_f1: micro,
+            classes,
+            confusion: cm.counts.clone(),
+        }
+    }
+
+    /// Metrics per class and averages as text table
+    pub fn table(&self) -> String {
+        let mut table = format!(
+            "{:>10}{:>10}{:>10}{:>10}{:>10}\n",
+            "class", "precision", "recall", "f1", "support"
+        );
+        for c in &self.classes {
+            table += format!(
+                "{:>10}{:>10}{:>10}{:>10}{:>10}\n",
+                c.label,
+                format_metric(c.precision),
+                format_metric(c.recall),
+                format_metric(c.f1),
+                c.support
+            )
+            .as_str();
+        }
+        for (name, p, r, f) in [
+            (
+                "macro",
+                self.macro_precision,
+                self.macro_recall,
+                self.macro_f1,
+            ),
+            (
+                "micro",
+                self.micro_precision,
+                self.micro_recall,
+                self.micro_f1,
+            ),
+        ] {
+            table += format!(
+                "{:>10}{:>10}{:>10}{:>10}{:>10}\n",
+                name,
+                format_metric(p),
+                format_metric(r),
+                format_metric(f),
+                self.samples
+            )
+            .as_str();
+        }
+        table += format!(
+            "accuracy: {}, kappa: {}\n",
+            format_metric(self.accuracy),
+            format_metric(self.kappa)
+        )
+        .as_str();
+        table
+    }
+}
+
+/// Mean of the defined values, `None` if there are none
+fn mean(values: impl Iterator<Item = Option<Float>>) -> Option<Float> {
+    let defined: Vec<Float> = values.flatten().collect();
+    if defined.is_empty() {
+        None
+    } else {
+        Some(defined.iter().sum::<Float>() / defined.len() as Float)
+    }
+}
+
+/// Ratio of two counts, `None` if the denominator is zero
+pub fn ratio(a: usize, b: usize) -> Option<Float> {
+    if b == 0 {
+        None
+    } else {
+        Some(a as Float / b as Float)
+    }
+}
+
+/// Formats an optional metric, `-` if undefined
+pub fn format_metric(value: Option<Float>) -> String {
+    value.map_or("-".to_owned(), |v| format!("{:.3}", v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confusion_matrix() {
+        let mut cm = ConfusionMatrix::new(3);
+        for (t, p) in [(0, 0), (1, 1), (1, 2), (2, 2), (2, 7)] {
+            cm.add(t, p);
+        }
+        assert_eq!(cm.total(), 5);
+        assert_eq!(cm.correct(), 4);
+        assert_eq!(cm.precision(2), Some(2.0 / 3.0));
+        assert_eq!(cm.recall(1), Some(0.5));
+        assert_eq!(ConfusionMatrix::new(2).accuracy(), None);
+        assert!(cm
+            .table(BOND_CLASSES)
+            .starts_with(" true\\pred      none    single    double\n"));
+    }
+    #[test]
+    fn test_report() {
+        let labels = [0.0, 0.0, 0.0, 1.0, 1.0, 2.0];
+        let preds = [0.0, 0.0, 1.0, 1.0, 1.0, 0.0];
+        let cm = ConfusionMatrix::from_labels(&labels, &preds, 3);
+        assert!((cm.f1(1).unwrap() - 0.8).abs() < 1e-6);
+        assert_eq!(cm.f1(2), None);
+        // observed 4/6, expected (3*3 + 2*3 + 1*0) / 36
+        let kappa = cm.kappa().unwrap();
+        assert!((kappa - (4.0 / 6.0 - 15.0 / 36.0) / (1.0 - 15.0 / 36.0)).abs() < 1e-6);
+        let report = ClassificationReport::new(&cm, BOND_CLASSES);
+        assert_eq!(report.samples, 6);
+        assert!((report.macro_recall.unwrap() - (2.0 / 3.0 + 1.0 + 0.0) / 3.0).abs() < 1e-6);
+        assert_eq!(report.micro_f1, report.accuracy);
+        let value = serde_json::to_value(&report).unwrap();
+        assert_eq!(value["classes"][2]["precision"], serde_json::Value::Null);
+        assert_eq!(value["confusion"][0][1], 1);
+    }
+}
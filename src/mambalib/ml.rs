@@ -1,92 +1,262 @@
 extern crate xgboost;
+use std::collections::BTreeMap;
+use std::error::Error;
 use std::fs;
 
 use xgboost::{parameters, Booster, DMatrix};
 
 use polars::prelude::*;
 
+use crate::breakdown::{breakdown, breakdown_table, GroupAccuracy};
+use crate::metadata::{check_model, dataset_config, ModelMetadata};
+use crate::metrics::{ClassificationReport, ConfusionMatrix, BOND_CLASSES};
+use crate::trees::class_probabilities;
 use crate::{
-    create_dataframe,
+    create_dataframe, feature_names,
     utils::{accuracy, df2vec},
-    XYZMolecule,
+    FeatureConfig, XYZMolecule,
 };
 
-pub fn train_xgb(trainpath: &str, testpath: &str) {
-    //this function needs libsvm data sets
-    //let dtrain = DMatrix::load("../mamba/3dqsar_train.dat").unwrap();
-    let dtrain = DMatrix::load(trainpath).unwrap();
-    println!("Train matrix: {}x{}", dtrain.num_rows(), dtrain.num_cols());
-    let dtest = DMatrix::load(testpath).unwrap();
-    println!("Test matrix: {}x{}", dtest.num_rows(), dtest.num_cols());
+pub use crate::metadata::TrainParams;
 
+/// Metric of the evaluation set used for early stopping, lower is better
+pub const STOPPING_METRIC: &str = "mlogloss";
+
+/// Booster configuration of the bond class model
+fn booster_parameters(
+    params: &TrainParams,
+) -> Result<parameters::BoosterParameters, Box<dyn Error>> {
     // configure objectives, metrics, etc.
     let learning_params = parameters::learning::LearningTaskParametersBuilder::default()
         .objective(parameters::learning::Objective::MultiSoftmax(5))
-        .build()
-        .unwrap();
+        .eval_metrics(parameters::learning::Metrics::Custom(vec![
+            parameters::learning::EvaluationMetric::MultiClassLogLoss,
+            parameters::learning::EvaluationMetric::MultiClassErrorRate,
+        ]))
+        .build()?;
 
     // configure the tree-based learning model's parameters
     let tree_params = parameters::tree::TreeBoosterParametersBuilder::default()
-        .max_depth(6)
-        .eta(0.1)
-        .build()
-        .unwrap();
+        .max_depth(params.max_depth)
+        .eta(params.eta)
+        .subsample(params.subsample)
+        .colsample_bytree(params.colsample_bytree)
+        .min_child_weight(params.min_child_weight)
+        .build()?;
 
     // overall configuration for Booster
-    let booster_params = parameters::BoosterParametersBuilder::default()
+    Ok(parameters::BoosterParametersBuilder::default()
         .booster_type(parameters::BoosterType::Tree(tree_params))
         .learning_params(learning_params)
         .verbose(false)
-        .build()
-        .unwrap();
-    // specify datasets to evaluate against during training
-    let evaluation_sets = [(&dtest, "test"), (&dtrain, "train")];
+        .build()?)
+}
+
+/// Trains a bond class model, metrics of the evaluation sets are printed every round
+pub fn train_booster(
+    dtrain: &DMatrix,
+    params: &TrainParams,
+    evaluation_sets: Option<&[(&DMatrix, &str)]>,
+) -> Result<Booster, Box<dyn Error>> {
     // overall configuration for training/evaluation
     let training_params = parameters::TrainingParametersBuilder::default()
-        .dtrain(&dtrain) // dataset to train with
-        .boost_rounds(200) // number of training iterations
-        .booster_params(booster_params) // model parameters
-        .evaluation_sets(Some(&evaluation_sets)) // optional datasets to evaluate against in each iteration
-        .build()
-        .unwrap();
+        .dtrain(dtrain) // dataset to train with
+        .boost_rounds(params.rounds) // number of training iterations
+        .booster_params(booster_parameters(params)?) // model parameters
+        .evaluation_sets(evaluation_sets) // optional datasets to evaluate against in each iteration
+        .build()?;
+    Ok(Booster::train(&training_params)?)
+}
 
-    // train booster model, and print evaluation metrics
-    println!("\nTraining tree booster...");
-    let booster = Booster::train(&training_params).unwrap();
+/// Train and test metrics after a boosting round
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoundMetrics {
+    /// one based, the number of trees per class
+    pub round: u32,
+    pub train: BTreeMap<String, f32>,
+    pub test: BTreeMap<String, f32>,
+}
 
-    // save and load model file
-    println!("\nSaving and loading Booster model...");
-    booster.save("xgb.model").unwrap();
+/// Training curve and the round of the saved model
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TrainingLog {
+    pub rounds: Vec<RoundMetrics>,
+    /// round with the lowest `STOPPING_METRIC` on the test set
+    pub best_round: u32,
+    /// true if training stopped before the maximum number of rounds
+    pub stopped_early: bool,
+}
 
-    // get predictions probabilities for given matrix
-    let preds = booster.predict(&dtrain).unwrap();
+impl TrainingLog {
+    /// Metrics of the best round
+    pub fn best(&self) -> Option<&RoundMetrics> {
+        self.rounds.iter().find(|r| r.round == self.best_round)
+    }
 
-    // get predicted labels for each test example (i.e. 0 or 1)
-    println!("\nChecking predictions...");
-    let labels = dtrain.get_labels().unwrap();
-    for (t, p) in labels.iter().zip(preds.iter()) {
-        println!("t:{} p:{}", t, p);
+    /// Training curve as CSV with a train and a test column per metric
+    pub fn csv(&self) -> String {
+        let names: Vec<&String> = self
+            .rounds
+            .first()
+            .map(|r| r.train.keys().collect())
+            .unwrap_or_default();
+        let mut csv = String::from("round");
+        for set in ["train", "test"] {
+            for name in &names {
+                csv += format!(",{}_{}", set, name).as_str();
+            }
+        }
+        csv += "\n";
+        for r in &self.rounds {
+            csv += r.round.to_string().as_str();
+            for metrics in [&r.train, &r.test] {
+                for name in &names {
+                    let value = metrics.get(*name).map_or(String::new(), |v| v.to_string());
+                    csv += format!(",{}", value).as_str();
+                }
+            }
+            csv += "\n";
+        }
+        csv
     }
+}
 
-    let acc = accuracy(&preds, labels);
-    println!(
-        "accuracy={}% ({}/{} correct)",
-        acc,
-        acc * preds.len() as f32,
-        preds.len()
+/// Trains a bond class model and stops once the test metric has not improved for
+/// `patience` rounds, the model of the best round is saved to `model`
+///
+/// The model of the first round is always saved, also if the metric is never finite.
+pub fn train_early_stopping(
+    dtrain: &DMatrix,
+    dtest: &DMatrix,
+    params: &TrainParams,
+    patience: Option<u32>,
+    model: &str,
+) -> Result<TrainingLog, Box<dyn Error>> {
+    let mut booster =
+        Booster::new_with_cached_dmats(&booster_parameters(params)?, &[dtrain, dtest])?;
+    let mut log = TrainingLog::default();
+    let mut best_score = f32::INFINITY;
+    for i in 0..params.rounds {
+        booster.update(dtrain, i as i32)?;
+        let round = RoundMetrics {
+            round: i + 1,
+            train: booster.evaluate(dtrain)?.into_iter().collect(),
+            test: booster.evaluate(dtest)?.into_iter().collect(),
+        };
+        let score = *round
+            .test
+            .get(STOPPING_METRIC)
+            .ok_or_else(|| format!("No {} in the evaluation", STOPPING_METRIC))?;
+        eprintln!(
+            "[{}] train-{}:{} test-{}:{}",
+            round.round, STOPPING_METRIC, round.train[STOPPING_METRIC], STOPPING_METRIC, score
+        );
+        if !score.is_finite() {
+            eprintln!(
+                "Warning: test {} is {} in round {}",
+                STOPPING_METRIC, score, round.round
+            );
+        }
+        log.rounds.push(round);
+        if i == 0 || score < best_score {
+            // a NaN of the first round must not hide the improvements of later rounds
+            best_score = if score.is_nan() { f32::INFINITY } else { score };
+            log.best_round = i + 1;
+            // the booster can not be cut back to an earlier round, so the best one is kept on disk
+            booster.save(model)?;
+        } else if patience.map_or(false, |p| i + 1 - log.best_round >= p) {
+            log.stopped_early = true;
+            break;
+        }
+    }
+    Ok(log)
+}
+
+/// Checks that a data set has the columns of the feature config, e.g. that it was not
+/// featurised with other flags than given for the training
+pub fn check_columns(
+    data: &DMatrix,
+    path: &str,
+    config: &FeatureConfig,
+) -> Result<(), Box<dyn Error>> {
+    let expected = feature_names(config).len();
+    if data.num_cols() != expected {
+        return Err(format!(
+            "{} has {} feature columns, the feature config {:?} has {}",
+            path,
+            data.num_cols(),
+            config,
+            expected
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Trains on a libsvm data set with early stopping on a second one, saves the best model
+/// with its metadata, `config` is the featurisation of the data sets
+pub fn train_xgb(
+    trainpath: &str,
+    testpath: &str,
+    config: &FeatureConfig,
+    params: &TrainParams,
+    patience: Option<u32>,
+    model: &str,
+) -> Result<TrainingLog, Box<dyn Error>> {
+    let dtrain = DMatrix::load(trainpath)?;
+    eprintln!("Train matrix: {}x{}", dtrain.num_rows(), dtrain.num_cols());
+    check_columns(&dtrain, trainpath, config)?;
+    let dtest = DMatrix::load(testpath)?;
+    eprintln!("Test matrix: {}x{}", dtest.num_rows(), dtest.num_cols());
+    check_columns(&dtest, testpath, config)?;
+
+    eprintln!("\nTraining tree booster...");
+    let log = train_early_stopping(&dtrain, &dtest, params, patience, model)?;
+    if log.stopped_early {
+        eprintln!("Stopped early after {} rounds", log.rounds.len());
+    }
+    let best = log.best().ok_or("No boosting rounds")?;
+    eprintln!(
+        "Best round {}: {:?}, saved to {}",
+        best.round, best.test, model
     );
+    let mut metadata = ModelMetadata::new(*config).with_training_data(trainpath)?;
+    metadata.params = Some(TrainParams {
+        rounds: best.round,
+        ..params.clone()
+    });
+    // JSON has no NaN, non-finite metrics are left out
+    metadata.metrics = best
+        .test
+        .iter()
+        .filter(|(_, value)| value.is_finite())
+        .map(|(name, value)| (format!("test_{}", name), *value))
+        .collect();
+    metadata.save(model)?;
+    Ok(log)
 }
 
-pub fn eval_xgb(evaldata: &str) {
+/// Metrics of the model on an evaluation data set
+pub struct Evaluation {
+    pub report: ClassificationReport,
+    /// accuracy by element pair and by distance bin
+    pub breakdown: Vec<GroupAccuracy>,
+}
+
+/// Evaluates a model on a libsvm data set, prints and returns the metrics
+pub fn eval_xgb(evaldata: &str, model: &str) -> Result<Evaluation, Box<dyn Error>> {
     println!("\nLoading eval data set...");
-    let dtest = DMatrix::load(evaldata).unwrap();
+    let dtest = DMatrix::load(evaldata)?;
     println!("\nLoading xgb model...");
-    let booster = Booster::load("xgb.model").unwrap();
+    let (booster, config) = load_model_with_config(model)?;
+    // the data set is already featurised, its config only gives the distance bins
+    let config = dataset_config(evaldata)?.unwrap_or(config);
+    check_columns(&dtest, evaldata, &config)?;
     // get predictions probabilities for given matrix
-    let preds = booster.predict(&dtest).unwrap();
+    let preds = booster.predict(&dtest)?;
 
     // get predicted labels for each test example (i.e. 0 or 1)
-    let labels = dtest.get_labels().unwrap();
+    let labels = dtest.get_labels()?;
 
     let acc = accuracy(&preds, labels);
     println!(
@@ -95,28 +265,95 @@ pub fn eval_xgb(evaldata: &str) {
         acc * preds.len() as f32,
         preds.len()
     );
+    let cm = ConfusionMatrix::from_labels(labels, &preds, BOND_CLASSES.len());
+    println!("\n{}", cm.table(BOND_CLASSES));
+    let report = ClassificationReport::new(&cm, BOND_CLASSES);
+    println!("{}", report.table());
+
+    let contents = fs::read_to_string(evaldata)?;
+    let breakdown = breakdown(&contents, labels, &preds, config.dist_cutoff)?;
+    println!("{}", breakdown_table(&breakdown));
+    Ok(Evaluation { report, breakdown })
+}
+
+/// Loads a xgboost model together with the feature config it was trained with, to be
+/// reused for several predictions, models whose metadata records a featurisation
+/// different from that of this build are refused
+pub fn load_model_with_config(model: &str) -> Result<(Booster, FeatureConfig), Box<dyn Error>> {
+    eprintln!("Loading xgb-model:{}", model);
+    let config = check_model(model)?;
+    Ok((Booster::load(model)?, config))
 }
 
-pub fn predict_mol(mol: &XYZMolecule) -> DataFrame {
-    let df = create_dataframe(mol).unwrap();
+/// Loads a xgboost model from memory, e.g. a model embedded in the binary
+pub fn load_model_from_buffer(bytes: &[u8]) -> Result<Booster, Box<dyn Error>> {
+    Ok(Booster::load_buffer(bytes)?)
+}
 
-    let model = "xgb.model";
-    println!("Loading xgb-model:{}", model);
-    let booster = Booster::load(model).unwrap();
+/// Predicts bond orders and their probabilities with an already loaded model,
+/// `config` is the featurisation the model was trained with
+pub fn predict_with_config(
+    booster: &Booster,
+    mol: &XYZMolecule,
+    config: &FeatureConfig,
+) -> Result<DataFrame, Box<dyn Error>> {
+    let df = create_dataframe(mol, config)?;
 
     let flat_vec = df2vec(&df);
 
     let (n, _) = df.shape();
-    let dtest = DMatrix::from_dense(&flat_vec, n).unwrap();
-    let preds = Series::new("preds", booster.predict(&dtest).unwrap());
-
-    let df = df.hstack(&[preds]).unwrap();
-
-    let file = fs::File::create("df.csv").expect("could not create file");
-    CsvWriter::new(&file)
-        .has_header(true)
-        .with_delimiter(b',')
-        .finish(&df)
-        .unwrap();
-    df
+    let dtest = DMatrix::from_dense(&flat_vec, n)?;
+    let preds = booster.predict(&dtest)?;
+    let margins = booster.predict_margin(&dtest)?;
+    let probs = class_probabilities(&margins, &preds);
+    let preds = Series::new("preds", preds);
+    let probs = Series::new("prob", probs);
+
+    Ok(df.hstack(&[preds, probs])?)
+}
+
+/// Predicts the bonds of a molecule with the shipped `xgb.model`
+pub fn predict_mol(mol: &XYZMolecule) -> Result<DataFrame, Box<dyn Error>> {
+    let (booster, config) = load_model_with_config("xgb.model")?;
+    predict_with_config(&booster, mol, &config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_training_log_csv() {
+        let metrics = |logloss: f32, error: f32| -> BTreeMap<String, f32> {
+            vec![
+                ("mlogloss".to_owned(), logloss),
+                ("merror".to_owned(), error),
+            ]
+            .into_iter()
+            .collect()
+        };
+        let log = TrainingLog {
+            rounds: vec![
+                RoundMetrics {
+                    round: 1,
+                    train: metrics(0.5, 0.25),
+                    test: metrics(0.75, 0.5),
+                },
+                RoundMetrics {
+                    round: 2,
+                    train: metrics(0.25, 0.125),
+                    test: metrics(1.0, 0.5),
+                },
+            ],
+            best_round: 1,
+            stopped_early: true,
+        };
+        assert_eq!(log.best().unwrap().test["mlogloss"], 0.75);
+        assert_eq!(
+            log.csv(),
+            "round,train_merror,train_mlogloss,test_merror,test_mlogloss\n\
+             1,0.25,0.5,0.5,0.75\n\
+             2,0.125,0.25,0.5,1\n"
+        );
+    }
 }
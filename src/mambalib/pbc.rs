@@ -0,0 +1,236 @@
+//! Periodic boundary conditions.
+//!
+//! The lattice is a 3x3 array with the cell vectors as rows, as in the extended
+//! xyz property `Lattice="ax ay az bx by bz cx cy cz"`. Distances follow the
+//! minimum image convention: the difference vector is wrapped into the cell in
+//! fractional coordinates and the shortest of its 27 neighboring images is taken,
+//! which for skewed cells need not be the wrapped one. A pair only has one image
+//! within the distance cut off if the cell is at least twice as wide, narrower
+//! cells are refused by `create_dataframe`.
+
+use std::error::Error;
+
+use ndarray::{Array2, ArrayView1};
+
+use crate::{Float, XYZMolecule};
+
+/// Parses 9 numbers (cell vectors as rows) or 3 numbers (orthorhombic cell),
+/// separated by whitespace or commas
+pub fn parse_lattice(s: &str) -> Result<Array2<Float>, Box<dyn Error>> {
+    let values = s
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse::<Float>())
+        .collect::<Result<Vec<Float>, _>>()?;
+    match values.len() {
+        9 => Ok(Array2::from_shape_vec((3, 3), values)?),
+        3 => Ok(Array2::from_diag(&ndarray::arr1(&values))),
+        n => Err(format!("Lattice needs 3 or 9 numbers, got {}", n).into()),
+    }
+}
+
+/// Periodic cell with the inverse lattice for fractional coordinates
+pub struct Cell {
+    pub lattice: Array2<Float>,
+    inverse: Array2<Float>,
+}
+
+impl Cell {
+    pub fn new(lattice: &Array2<Float>) -> Result<Self, Box<dyn Error>> {
+        if lattice.shape() != [3, 3] {
+            return Err(format!("Lattice must be 3x3, got {:?}", lattice.shape()).into());
+        }
+        let m = lattice;
+        let det = m[[0, 0]] * (m[[1, 1]] * m[[2, 2]] - m[[1, 2]] * m[[2, 1]])
+            - m[[0, 1]] * (m[[1, 0]] * m[[2, 2]] - m[[1, 2]] * m[[2, 0]])
+            + m[[0, 2]] * (m[[1, 0]] * m[[2, 1]] - m[[1, 1]] * m[[2, 0]]);
+        if det.abs() < 1e-6 {
+            return Err("Lattice vectors are linearly dependent".into());
+        }
+        // inverse from the adjugate
+        let mut inverse = Array2::zeros((3, 3));
+        for i in 0..3 {
+            for j in 0..3 {
+                let (r1, r2) = ((j + 1) % 3, (j + 2) % 3);
+                let (c1, c2) = ((i + 1) % 3, (i + 2) % 3);
+                inverse[[i, j]] = (m[[r1, c1]] * m[[r2, c2]] - m[[r1, c2]] * m[[r2, c1]]) / det;
+            }
+        }
+        Ok(Cell {
+            lattice: lattice.to_owned(),
+            inverse,
+        })
+    }
+
+    /// Shortest periodic image of a difference vector
+    pub fn minimum_image(&self, diff: [Float; 3]) -> [Float; 3] {
+        // fractional coordinates f = d L^-1 with the cell vectors as rows of L
+        let mut frac = [0.0; 3];
+        for (k, f) in frac.iter_mut().enumerate() {
+            *f = (0..3)
+                .map(|i| diff[i] * self.inverse[[i, k]])
+                .sum::<Float>();
+            *f -= f.round();
+        }
+        let mut best = self.cartesian(frac);
+        let mut best_norm = norm2(best);
+        for na in -1..=1 {
+            for nb in -1..=1 {
+                for nc in -1..=1 {
+                    let shift = [na as Float, nb as Float, nc as Float];
+                    let image = self.cartesian([
+                        frac[0] + shift[0],
+                        frac[1] + shift[1],
+                        frac[2] + shift[2],
+                    ]);
+                    let n = norm2(image);
+                    if n < best_norm {
+                        best = image;
+                        best_norm = n;
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    /// Cartesian vector of fractional coordinates
+    fn cartesian(&self, frac: [Float; 3]) -> [Float; 3] {
+        let mut cart = [0.0; 3];
+        for (k, c) in cart.iter_mut().enumerate() {
+            *c = (0..3)
+                .map(|i| frac[i] * self.lattice[[i, k]])
+                .sum::<Float>();
+        }
+        cart
+    }
+
+    /// Smallest distance between opposite faces of the cell
+    pub fn width(&self) -> Float {
+        // the columns of the inverse are the reciprocal vectors, 1/|b_k| is the face distance
+        (0..3)
+            .map(|k| 1.0 / self.inverse.column(k).mapv(|x| x * x).sum().sqrt())
+            .fold(Float::INFINITY, Float::min)
+    }
+
+    /// Minimum image distance of two positions
+    pub fn distance(&self, a: &ArrayView1<Float>, b: &ArrayView1<Float>) -> Float {
+        norm2(self.minimum_image([b[0] - a[0], b[1] - a[1], b[2] - a[2]])).sqrt()
+    }
+
+    /// Minimum image distance matrix
+    pub fn distance_matrix(&self, coords: &Array2<Float>) -> Array2<Float> {
+        let n = coords.nrows();
+        let mut distmat = Array2::zeros((n, n));
+        for i in 0..n {
+            for j in i + 1..n {
+                let dist = self.distance(&coords.row(i), &coords.row(j));
+                distmat[[i, j]] = dist;
+                distmat[[j, i]] = dist;
+            }
+        }
+        distmat
+    }
+}
+
+fn norm2(v: [Float; 3]) -> Float {
+    v[0] * v[0] + v[1] * v[1] + v[2] * v[2]
+}
+
+/// Moves bonded atoms next to each other so that molecules crossing the cell
+/// boundary are whole again, e.g. before writing SDF
+pub fn unwrap(mol: &mut XYZMolecule, bonds: &[(usize, usize, u32)]) -> Result<(), Box<dyn Error>> {
+    let cell = match mol.cell()? {
+        Some(cell) => cell,
+        None => return Ok(()),
+    };
+    let mut neighbors = vec![Vec::<usize>::new(); mol.natoms];
+    for (i, j, order) in bonds {
+        if *order > 0 {
+            neighbors[*i].push(*j);
+            neighbors[*j].push(*i);
+        }
+    }
+    let mut visited = vec![false; mol.natoms];
+    for root in 0..mol.natoms {
+        if visited[root] {
+            continue;
+        }
+        visited[root] = true;
+        let mut stack = vec![root];
+        while let Some(i) = stack.pop() {
+            for j in neighbors[i].clone() {
+                if visited[j] {
+                    continue;
+                }
+                visited[j] = true;
+                let c = &mol.coords;
+                let d = cell.minimum_image([
+                    c[[j, 0]] - c[[i, 0]],
+                    c[[j, 1]] - c[[i, 1]],
+                    c[[j, 2]] - c[[i, 2]],
+                ]);
+                for k in 0..3 {
+                    mol.coords[[j, k]] = mol.coords[[i, k]] + d[k];
+                }
+                stack.push(j);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_lattice() {
+        let lattice = parse_lattice("10.0 0 0 0 10.0 0 0 0 12.0").unwrap();
+        assert_eq!(lattice[[2, 2]], 12.0);
+        assert_eq!(parse_lattice("10,10,12").unwrap(), lattice);
+        assert!(parse_lattice("10 10").is_err());
+    }
+    #[test]
+    fn test_minimum_image() {
+        // triclinic cell
+        let lattice = arr2(&[[10.0, 0.0, 0.0], [2.0, 10.0, 0.0], [0.0, 0.0, 10.0]]);
+        let cell = Cell::new(&lattice).unwrap();
+        let coords = arr2(&[[0.5, 0.5, 0.5], [9.8, 0.5, 9.7]]);
+        let dm = cell.distance_matrix(&coords);
+        assert!((dm[[0, 1]] - (0.7 as Float).hypot(0.8)).abs() < 1e-4);
+        assert!(Cell::new(&Array2::zeros((3, 3))).is_err());
+        // distance of the faces spanned by b and c
+        assert!((cell.width() - 100.0 / (104.0 as Float).sqrt()).abs() < 1e-3);
+    }
+    #[test]
+    fn test_skewed_cell() {
+        // rounding the fractional coordinates gives an image 3.0 A long
+        let lattice = arr2(&[[10.0, 0.0, 0.0], [8.0, 2.0, 0.0], [0.0, 0.0, 10.0]]);
+        let cell = Cell::new(&lattice).unwrap();
+        let d = cell.minimum_image([1.0, 1.9, 0.0]);
+        assert!((norm2(d).sqrt() - (1.0 as Float).hypot(1.9)).abs() < 1e-4);
+        assert!((cell.width() - 2.0).abs() < 1e-4);
+    }
+    #[test]
+    fn test_narrow_cell() {
+        let atoms = vec!["O".to_string(), "H".to_string()];
+        let coords = arr2(&[[0.2, 2.0, 2.0], [1.2, 2.0, 2.0]]);
+        let mut mol = XYZMolecule::new(atoms, coords, 0);
+        mol.lattice = Some(parse_lattice("5 5 5").unwrap());
+        assert!(crate::create_dataframe(&mol, &crate::FeatureConfig::default()).is_err());
+        mol.lattice = Some(parse_lattice("6 6 6").unwrap());
+        assert!(crate::create_dataframe(&mol, &crate::FeatureConfig::default()).is_ok());
+    }
+    #[test]
+    fn test_unwrap() {
+        let atoms = vec!["O".to_string(), "H".to_string()];
+        let coords = arr2(&[[0.2, 5.0, 5.0], [9.4, 5.0, 5.0]]);
+        let mut mol = XYZMolecule::new(atoms, coords, 0);
+        mol.lattice = Some(parse_lattice("10 10 10").unwrap());
+        unwrap(&mut mol, &[(0, 1, 1)]).unwrap();
+        assert!((mol.coords[[1, 0]] + 0.6).abs() < 1e-4);
+    }
+}
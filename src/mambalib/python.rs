@@ -0,0 +1,161 @@
+//! Python bindings, enabled with the `python` feature.
+//!
+//! Build with `maturin develop --features python`, then
+//!
+//! ```python
+//! import mambalib
+//! predictor = mambalib.Predictor("xgb.model")
+//! result = predictor.perceive(["C", "O"], coords, charge=0)
+//! result["bonds"], result["orders"], result["molblock"]
+//! ```
+//!
+//! Coordinates can be float64 or float32 arrays or nested lists. The tests of the
+//! bindings are in `tests/test_python.py` (`pytest tests` after `maturin develop`).
+
+use std::error::Error;
+
+use ndarray::Array2;
+use numpy::{AllowTypeChange, IntoPyArray, PyArray2, PyArrayLike2};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use xgboost::Booster;
+
+use crate::ml::{load_model_with_config, predict_with_config};
+use crate::utils::df2vec;
+use crate::valence::postprocess;
+use crate::{bond_orders, create_dataframe, create_molblock, FeatureConfig, Float, XYZMolecule};
+
+fn to_py_err(e: Box<dyn Error>) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+/// Coordinates as accepted from python: float64 or float32 arrays and nested lists
+type Coords<'py> = PyArrayLike2<'py, f64, AllowTypeChange>;
+
+/// Molecule from python inputs, checking the shape of the coordinates
+fn molecule(
+    elements: Vec<String>,
+    coords: Coords<'_>,
+    charge: i32,
+    multiplicity: u32,
+) -> PyResult<XYZMolecule> {
+    let coords = coords.as_array().mapv(|x| x as Float);
+    if coords.ncols() != 3 || coords.nrows() != elements.len() {
+        return Err(PyValueError::new_err(format!(
+            "coords must have shape ({}, 3), got {:?}",
+            elements.len(),
+            coords.shape()
+        )));
+    }
+    let mut mol = XYZMolecule::new(elements, coords, charge);
+    mol.multiplicity = multiplicity;
+    Ok(mol)
+}
+
+/// Bond predictor keeping the xgboost model loaded
+#[pyclass(unsendable)]
+pub struct Predictor {
+    booster: Booster,
+    config: FeatureConfig,
+}
+
+#[pymethods]
+impl Predictor {
+    #[new]
+    #[pyo3(signature = (model = "xgb.model"))]
+    fn new(model: &str) -> PyResult<Self> {
+        let (booster, config) = load_model_with_config(model).map_err(to_py_err)?;
+        Ok(Predictor { booster, config })
+    }
+
+    /// Perceives the bonds of a molecule.
+    ///
+    /// Returns a dict with `bonds` (n x 2 zero based atom indices), `orders`,
+    /// `probabilities`, `charges`, `radicals` and the `molblock` text.
+    #[pyo3(signature = (elements, coords, charge = 0, multiplicity = 1))]
+    fn perceive<'py>(
+        &self,
+        py: Python<'py>,
+        elements: Vec<String>,
+        coords: Coords<'py>,
+        charge: i32,
+        multiplicity: u32,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let mut mol = molecule(elements, coords, charge, multiplicity)?;
+        let df = predict_with_config(&self.booster, &mol, &self.config).map_err(to_py_err)?;
+        let df = postprocess(&mut mol, df).map_err(to_py_err)?;
+
+        let probs: Vec<Float> = df
+            .column("prob")
+            .and_then(|p| p.f32().map(|p| p.into_iter().map(|x| x.unwrap_or(1.0)).collect()))
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let mut pairs = Vec::<i64>::new();
+        let mut orders = Vec::<u32>::new();
+        let mut probabilities = Vec::<Float>::new();
+        for ((i, j, order), prob) in bond_orders(&df).map_err(to_py_err)?.into_iter().zip(probs) {
+            if order > 0 {
+                pairs.push(i.min(j) as i64);
+                pairs.push(i.max(j) as i64);
+                orders.push(order);
+                probabilities.push(prob);
+            }
+        }
+        let bonds = Array2::from_shape_vec((orders.len(), 2), pairs)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+        let dict = PyDict::new(py);
+        dict.set_item("bonds", bonds.into_pyarray(py))?;
+        dict.set_item("orders", orders.into_pyarray(py))?;
+        dict.set_item("probabilities", probabilities.into_pyarray(py))?;
+        dict.set_item("charges", mol.charges.clone().into_pyarray(py))?;
+        dict.set_item("radicals", mol.radicals.clone().into_pyarray(py))?;
+        dict.set_item("molblock", create_molblock(mol, df).map_err(to_py_err)?)?;
+        Ok(dict)
+    }
+}
+
+/// Feature table of `create_dataframe` as a 2D array together with the column names,
+/// `cutoff` and `neighbors` default to the featurisation of the shipped model, `angles`,
+/// `environment`, `radial` and `spin` (the multiplicity as a column) add the extended features
+#[pyfunction]
+#[pyo3(signature = (
+    elements, coords, charge = 0, cutoff = None, neighbors = None,
+    angles = false, environment = false, radial = false, multiplicity = 1, spin = false
+))]
+fn features<'py>(
+    py: Python<'py>,
+    elements: Vec<String>,
+    coords: Coords<'py>,
+    charge: i32,
+    cutoff: Option<Float>,
+    neighbors: Option<usize>,
+    angles: bool,
+    environment: bool,
+    radial: bool,
+    multiplicity: u32,
+    spin: bool,
+) -> PyResult<(Bound<'py, PyArray2<Float>>, Vec<String>)> {
+    let mol = molecule(elements, coords, charge, multiplicity)?;
+    let default = FeatureConfig::default();
+    let config = FeatureConfig {
+        dist_cutoff: cutoff.unwrap_or(default.dist_cutoff),
+        n_cut: neighbors.unwrap_or(default.n_cut),
+        angles,
+        environment,
+        radial,
+        multiplicity: spin,
+    };
+    let df = create_dataframe(&mol, &config).map_err(to_py_err)?;
+    let names = df.get_column_names().iter().map(|s| s.to_string()).collect();
+    let table = Array2::from_shape_vec(df.shape(), df2vec(&df))
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok((table.into_pyarray(py), names))
+}
+
+#[pymodule]
+fn mambalib(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Predictor>()?;
+    m.add_function(wrap_pyfunction!(features, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,308 @@
+//! Readers for the final geometries of quantum chemistry outputs.
+//!
+//! Supported are Gaussian `.log`, ORCA `.out` and xtb output files.
+//! Besides the coordinates the total charge and spin multiplicity are
+//! taken from the output, so they end up in the feature table.
+
+use std::error::Error;
+
+use ndarray::Array2;
+
+use crate::elements::Element;
+use crate::units::BOHR;
+use crate::{Float, XYZMolecule};
+
+/// Output formats of quantum chemistry codes we can read
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QMFormat {
+    Gaussian,
+    Orca,
+    Xtb,
+}
+
+/// Guess the program which has written the output from its banner
+pub fn detect_format(contents: &str) -> Option<QMFormat> {
+    if contents.contains("Entering Gaussian System") || contents.contains("Gaussian, Inc.") {
+        Some(QMFormat::Gaussian)
+    } else if contents.contains("O   R   C   A") {
+        Some(QMFormat::Orca)
+    } else if contents.contains("x T B") {
+        Some(QMFormat::Xtb)
+    } else {
+        None
+    }
+}
+
+/// Parse the final geometry of a quantum chemistry output, detecting the program
+pub fn mol_from_qm_string(contents: &str) -> Result<XYZMolecule, Box<dyn Error>> {
+    match detect_format(contents) {
+        Some(QMFormat::Gaussian) => mol_from_gaussian_string(contents),
+        Some(QMFormat::Orca) => mol_from_orca_string(contents),
+        Some(QMFormat::Xtb) => mol_from_xtb_string(contents),
+        None => Err("Unknown quantum chemistry output format".into()),
+    }
+}
+
+/// Final geometry from a Gaussian log file
+///
+/// Uses the last `Standard orientation` (or `Input orientation` for nosymm runs) block.
+pub fn mol_from_gaussian_string(contents: &str) -> Result<XYZMolecule, Box<dyn Error>> {
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut q: i32 = 0;
+    let mut multiplicity: u32 = 1;
+    let mut last_block: Option<usize> = None;
+    for (i, line) in lines.iter().enumerate() {
+        if line.contains("Charge =") && line.contains("Multiplicity =") {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            q = tokens[2].parse()?;
+            multiplicity = tokens[5].parse()?;
+        }
+        if line.contains("Standard orientation:") || line.contains("Input orientation:") {
+            last_block = Some(i);
+        }
+    }
+    let start = last_block.ok_or("No orientation block found in Gaussian output")?;
+
+    let mut atoms: Vec<String> = Vec::new();
+    let mut coords: Vec<Float> = Vec::new();
+    // header: dashes, two title lines, dashes
+    for line in lines.iter().skip(start + 5) {
+        if line.trim_start().starts_with("---") {
+            break;
+        }
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.len() < 6 {
+            return Err(format!("Malformed Gaussian coordinate line: {}", line).into());
+        }
+        let an: usize = tokens[1].parse()?;
+        atoms.push(symbol_from_number(an)?);
+        for t in &tokens[3..6] {
+            coords.push(t.parse()?);
+        }
+    }
+    build_molecule(atoms, coords, q, multiplicity)
+}
+
+/// Final geometry from an ORCA output file
+///
+/// Uses the last `CARTESIAN COORDINATES (ANGSTROEM)` block.
+pub fn mol_from_orca_string(contents: &str) -> Result<XYZMolecule, Box<dyn Error>> {
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut q: i32 = 0;
+    let mut multiplicity: u32 = 1;
+    let mut last_block: Option<usize> = None;
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("Total Charge") && trimmed.contains("....") {
+            q = last_token(trimmed).parse()?;
+        }
+        if trimmed.starts_with("Multiplicity") && trimmed.contains("....") {
+            multiplicity = last_token(trimmed).parse()?;
+        }
+        if trimmed == "CARTESIAN COORDINATES (ANGSTROEM)" {
+            last_block = Some(i);
+        }
+    }
+    let start = last_block.ok_or("No cartesian coordinates found in ORCA output")?;
+
+    let mut atoms: Vec<String> = Vec::new();
+    let mut coords: Vec<Float> = Vec::new();
+    // header: dashes
+    for line in lines.iter().skip(start + 2) {
+        if line.trim().is_empty() {
+            break;
+        }
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.len() < 4 {
+            return Err(format!("Malformed ORCA coordinate line: {}", line).into());
+        }
+        atoms.push(tokens[0].to_owned());
+        for t in &tokens[1..4] {
+            coords.push(t.parse()?);
+        }
+    }
+    build_molecule(atoms, coords, q, multiplicity)
+}
+
+/// Final geometry from an xtb output file
+///
+/// Uses the `final structure:` block, which is written in the format of the input,
+/// i.e. either xyz (Angstrom) or Turbomole `$coord` (Bohr).
+/// Charge and unpaired electrons are taken from the program call (`--chrg`, `--uhf`).
+pub fn mol_from_xtb_string(contents: &str) -> Result<XYZMolecule, Box<dyn Error>> {
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut q: i32 = 0;
+    let mut multiplicity: u32 = 1;
+    let mut last_block: Option<usize> = None;
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("program call") {
+            let mut iter = trimmed.split_whitespace();
+            while let Some(token) = iter.next() {
+                match token {
+                    "-c" | "--chrg" => q = iter.next().unwrap_or_default().parse()?,
+                    "-u" | "--uhf" => {
+                        let uhf: u32 = iter.next().unwrap_or_default().parse()?;
+                        multiplicity = uhf + 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if trimmed == "final structure:" {
+            last_block = Some(i);
+        }
+    }
+    let start = last_block.ok_or("No final structure found in xtb output")?;
+
+    let mut atoms: Vec<String> = Vec::new();
+    let mut coords: Vec<Float> = Vec::new();
+    // skip the underline of the block title
+    let mut block = lines
+        .iter()
+        .skip(start + 1)
+        .map(|l| l.trim())
+        .filter(|l| !l.starts_with("==="));
+    let first = block.next().ok_or("Empty final structure in xtb output")?;
+    if first.starts_with("$coord") {
+        for line in block {
+            if line.starts_with('$') {
+                break;
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if tokens.len() < 4 {
+                return Err(format!("Malformed xtb coordinate line: {}", line).into());
+            }
+            for t in &tokens[0..3] {
+                let x: Float = t.parse()?;
+                coords.push(x * BOHR);
+            }
+            atoms.push(capitalize(tokens[3]));
+        }
+    } else {
+        let natoms: usize = first.parse()?;
+        // comment line
+        block.next();
+        for line in block.take(natoms) {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if tokens.len() < 4 {
+                return Err(format!("Malformed xtb coordinate line: {}", line).into());
+            }
+            atoms.push(tokens[0].to_owned());
+            for t in &tokens[1..4] {
+                coords.push(t.parse()?);
+            }
+        }
+    }
+    build_molecule(atoms, coords, q, multiplicity)
+}
+
+fn build_molecule(
+    atoms: Vec<String>,
+    coords: Vec<Float>,
+    q: i32,
+    multiplicity: u32,
+) -> Result<XYZMolecule, Box<dyn Error>> {
+    if atoms.is_empty() {
+        return Err("No atoms found in quantum chemistry output".into());
+    }
+    let coords = Array2::from_shape_vec((atoms.len(), 3), coords)?;
+    let mut mol = XYZMolecule::new(atoms, coords, q);
+    mol.multiplicity = multiplicity;
+    // the readers convert Bohr coordinates themselves
+    mol.angstrom = true;
+    Ok(mol)
+}
+
+fn symbol_from_number(an: usize) -> Result<String, Box<dyn Error>> {
+    Ok(Element::from_atomic_number(an)?.symbol().to_owned())
+}
+
+fn last_token(line: &str) -> &str {
+    line.split_whitespace().last().unwrap_or_default()
+}
+
+/// Turbomole writes lower case element symbols
+fn capitalize(symbol: &str) -> String {
+    let mut chars = symbol.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().collect::<String>() + chars.as_str().to_lowercase().as_str(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAUSSIAN: &str = " Entering Gaussian System, Link 0=g16
+ Charge =  1 Multiplicity = 2
+                         Standard orientation:
+ ---------------------------------------------------------------------
+ Center     Atomic      Atomic             Coordinates (Angstroms)
+ Number     Number       Type             X           Y           Z
+ ---------------------------------------------------------------------
+      1          6           0        0.000000    0.000000    0.000000
+      2          8           0        0.000000    0.000000    1.200000
+ ---------------------------------------------------------------------
+";
+
+    const ORCA: &str = "                                 * O   R   C   A *
+ Total Charge           Charge          ....    -1
+ Multiplicity           Mult            ....    1
+---------------------------------
+CARTESIAN COORDINATES (ANGSTROEM)
+---------------------------------
+  O      0.000000    0.000000    0.000000
+  H      0.000000    0.000000    0.970000
+
+";
+
+    const XTB: &str = "      |                           x T B                           |
+          program call               : xtb mol.xyz --opt --chrg 0 --uhf 2
+final structure:
+================
+3
+ energy: -5.07 gnorm: 0.0001 xtb: 6.4.1
+O            0.00000000000000        0.00000000000000        0.00000000000000
+H            0.00000000000000        0.00000000000000        0.96000000000000
+H            0.93000000000000        0.00000000000000       -0.24000000000000
+";
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(detect_format(GAUSSIAN), Some(QMFormat::Gaussian));
+        assert_eq!(detect_format(ORCA), Some(QMFormat::Orca));
+        assert_eq!(detect_format(XTB), Some(QMFormat::Xtb));
+        assert_eq!(detect_format("2\n\nC 0 0 0\nO 0 0 1"), None);
+    }
+    #[test]
+    fn test_gaussian() {
+        let mol = mol_from_qm_string(GAUSSIAN).expect("Failed parsing!");
+        assert_eq!(mol.atoms, vec!["C", "O"]);
+        assert_eq!(mol.q, 1);
+        assert_eq!(mol.multiplicity, 2);
+        assert_eq!(mol.coords[[1, 2]], 1.2);
+    }
+    #[test]
+    fn test_orca() {
+        let mol = mol_from_qm_string(ORCA).expect("Failed parsing!");
+        assert_eq!(mol.natoms, 2);
+        assert_eq!(mol.q, -1);
+        assert_eq!(mol.multiplicity, 1);
+    }
+    #[test]
+    fn test_xtb() {
+        let mol = mol_from_qm_string(XTB).expect("Failed parsing!");
+        assert_eq!(mol.atoms, vec!["O", "H", "H"]);
+        assert_eq!(mol.q, 0);
+        assert_eq!(mol.multiplicity, 3);
+    }
+    #[test]
+    fn test_xtb_coord() {
+        let contents = "x T B\nfinal structure:\n================\n$coord\n 0.0 0.0 0.0 o\n 0.0 0.0 1.8 h\n$end\n";
+        let mol = mol_from_xtb_string(contents).expect("Failed parsing!");
+        assert_eq!(mol.atoms, vec!["O", "H"]);
+        assert!((mol.coords[[1, 2]] - 1.8 * BOHR).abs() < 1e-6);
+    }
+}
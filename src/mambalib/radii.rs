@@ -0,0 +1,166 @@
+//! Connectivity from covalent radii, without the model.
+//!
+//! Two atoms are bonded if their distance is below the sum of their covalent
+//! radii plus a tolerance. Only single bonds are assigned, so this is a baseline
+//! to compare the model against and a fallback for inputs the model has not seen
+//! in training, e.g. elements outside its feature table.
+
+use std::error::Error;
+use std::str::FromStr;
+
+use polars::prelude::*;
+#[cfg(not(target_arch = "wasm32"))]
+use xgboost::Booster;
+
+use crate::elements::covalent_radius;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::ml::predict_with_config;
+use crate::validate::{check_molecule, Check};
+use crate::{element_index, FeatureConfig, Float, XYZMolecule};
+
+/// Default tolerance in Angstrom added to the covalent radii sum
+pub const DEFAULT_TOLERANCE: Float = 0.45;
+
+/// Method of the bond perception
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    /// bond orders predicted by the model
+    Model,
+    /// single bonds from covalent radii
+    Radii,
+    /// model, covalent radii for molecules outside of the model's domain
+    Auto,
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "model" => Ok(Method::Model),
+            "radii" => Ok(Method::Radii),
+            "auto" => Ok(Method::Auto),
+            _ => Err(format!("Unknown method: {}", s)),
+        }
+    }
+}
+
+/// Bonds from covalent radii as dataframe with the columns of a model prediction
+///
+/// There is a row with `preds` 1 for every bonded pair, `prob` is always 1. Atoms
+/// without a covalent radius (unknown elements, e.g. dummy atoms) stay unbonded.
+pub fn predict_radii(mol: &XYZMolecule, tolerance: Float) -> Result<DataFrame, Box<dyn Error>> {
+    let dm = mol.distance_matrix()?;
+    let radii: Vec<Option<Float>> = mol.atoms.iter().map(|a| covalent_radius(a)).collect();
+    let mut id1 = Vec::<Float>::new();
+    let mut id2 = Vec::<Float>::new();
+    let mut dist = Vec::<Float>::new();
+    for i in 0..mol.natoms {
+        for j in i + 1..mol.natoms {
+            let (ri, rj) = match (radii[i], radii[j]) {
+                (Some(ri), Some(rj)) => (ri, rj),
+                _ => continue,
+            };
+            if dm[[i, j]] < ri + rj + tolerance {
+                id1.push(i as Float + 1.0);
+                id2.push(j as Float + 1.0);
+                dist.push(dm[[i, j]]);
+            }
+        }
+    }
+    let n = id1.len();
+    let df = DataFrame::new(vec![
+        Series::new("id1", id1),
+        Series::new("id2", id2),
+        Series::new("distab", dist),
+        Series::new("preds", vec![1.0 as Float; n]),
+        Series::new("prob", vec![1.0 as Float; n]),
+    ])?;
+    Ok(df)
+}
+
+/// Reason why the model should not be used for a molecule, if any
+///
+/// These are elements missing from the feature table and clashing atoms.
+pub fn outside_model_domain(mol: &XYZMolecule) -> Result<Option<String>, Box<dyn Error>> {
+    if let Some(symbol) = mol.atoms.iter().find(|a| element_index(a).is_err()) {
+        return Ok(Some(format!(
+            "element {} is not supported by the model",
+            symbol
+        )));
+    }
+    let clashes = check_molecule(mol, 0.5)?
+        .into_iter()
+        .filter(|issue| issue.check == Check::Clash || issue.check == Check::Duplicate)
+        .count();
+    if clashes > 0 {
+        return Ok(Some(format!("{} clashing atom pairs", clashes)));
+    }
+    Ok(None)
+}
+
+/// Bonds of a molecule with the given method together with the method used, `Radii` if
+/// `Auto` fell back to covalent radii
+///
+/// The model (with the feature config it was trained with) is not needed for the radii
+/// method, `source` names the molecule in the fallback warning. Only single bonds come
+/// from covalent radii, the valence post-processing would assign wrong charges and
+/// radicals to them and should be skipped for `Radii`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn predict_with_method(
+    mol: &XYZMolecule,
+    method: Method,
+    tolerance: Float,
+    model: Option<(&Booster, &FeatureConfig)>,
+    source: &str,
+) -> Result<(DataFrame, Method), Box<dyn Error>> {
+    let (booster, config) = match (method, model) {
+        (Method::Radii, _) => return Ok((predict_radii(mol, tolerance)?, Method::Radii)),
+        (_, Some(model)) => model,
+        (_, None) => return Err("The model is required for this method".into()),
+    };
+    if method == Method::Auto {
+        if let Some(reason) = outside_model_domain(mol)? {
+            eprintln!("Warning: {}: {}, using covalent radii", source, reason);
+            return Ok((predict_radii(mol, tolerance)?, Method::Radii));
+        }
+    }
+    Ok((predict_with_config(booster, mol, config)?, Method::Model))
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+
+    use super::*;
+    use crate::bond_orders;
+
+    #[test]
+    fn test_radii_water() {
+        let atoms = vec!["O".to_string(), "H".to_string(), "H".to_string()];
+        let coords = arr2(&[[0.0, 0.0, 0.0], [0.96, 0.0, 0.0], [-0.24, 0.93, 0.0]]);
+        let mol = XYZMolecule::new(atoms, coords, 0);
+        let df = predict_radii(&mol, DEFAULT_TOLERANCE).unwrap();
+        assert_eq!(bond_orders(&df).unwrap(), vec![(0, 1, 1), (0, 2, 1)]);
+        assert!(outside_model_domain(&mol).unwrap().is_none());
+    }
+    #[test]
+    fn test_outside_domain() {
+        let atoms = vec!["Po".to_string(), "H".to_string()];
+        let coords = arr2(&[[0.0, 0.0, 0.0], [0.0, 0.0, 1.7]]);
+        let mol = XYZMolecule::new(atoms, coords, 0);
+        assert!(outside_model_domain(&mol).unwrap().is_some());
+        assert_eq!(predict_radii(&mol, DEFAULT_TOLERANCE).unwrap().height(), 1);
+        assert_eq!("radii".parse::<Method>().unwrap(), Method::Radii);
+        // a dummy atom read from a file falls back to radii and stays unbonded
+        let mol = crate::mol_from_xyz_string("3\n\nO 0 0 0\nH 0.96 0 0\nXx 0 0.9 0").unwrap();
+        assert!(outside_model_domain(&mol).unwrap().is_some());
+        let df = predict_radii(&mol, DEFAULT_TOLERANCE).unwrap();
+        assert_eq!(bond_orders(&df).unwrap(), vec![(0, 1, 1)]);
+        let (booster, config) = crate::ml::load_model_with_config("xgb.model").unwrap();
+        let model = Some((&booster, &config));
+        let (_, used) =
+            predict_with_method(&mol, Method::Auto, DEFAULT_TOLERANCE, model, "test").unwrap();
+        assert_eq!(used, Method::Radii);
+    }
+}
@@ -0,0 +1,203 @@
+//! Hyperparameter search for the bond class model.
+//!
+//! The candidates are the grid of all given values or, for a random search, a
+//! random sample of that grid. Every candidate is scored by molecule-grouped
+//! cross-validation (see `crossval`), the same folds are used for all of them.
+
+use std::error::Error;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use xgboost::DMatrix;
+
+use crate::crossval::cross_validate_matrix;
+use crate::metrics::format_metric;
+use crate::ml::TrainParams;
+use crate::Float;
+
+/// Values to try for each hyperparameter
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchSpace {
+    pub max_depth: Vec<u32>,
+    pub eta: Vec<f32>,
+    pub subsample: Vec<f32>,
+    pub colsample_bytree: Vec<f32>,
+    pub min_child_weight: Vec<f32>,
+    pub rounds: Vec<u32>,
+}
+
+impl Default for SearchSpace {
+    /// Only the default parameters
+    fn default() -> Self {
+        let p = TrainParams::default();
+        SearchSpace {
+            max_depth: vec![p.max_depth],
+            eta: vec![p.eta],
+            subsample: vec![p.subsample],
+            colsample_bytree: vec![p.colsample_bytree],
+            min_child_weight: vec![p.min_child_weight],
+            rounds: vec![p.rounds],
+        }
+    }
+}
+
+impl SearchSpace {
+    /// All combinations of the values
+    pub fn grid(&self) -> Vec<TrainParams> {
+        let mut grid = Vec::<TrainParams>::new();
+        for &max_depth in &self.max_depth {
+            for &eta in &self.eta {
+                for &subsample in &self.subsample {
+                    for &colsample_bytree in &self.colsample_bytree {
+                        for &min_child_weight in &self.min_child_weight {
+                            for &rounds in &self.rounds {
+                                grid.push(TrainParams {
+                                    max_depth,
+                                    eta,
+                                    subsample,
+                                    colsample_bytree,
+                                    min_child_weight,
+                                    rounds,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    /// Random sample of `n` combinations, the whole grid if it is smaller
+    pub fn sample(&self, n: usize, seed: u64) -> Vec<TrainParams> {
+        let mut grid = self.grid();
+        grid.shuffle(&mut StdRng::seed_from_u64(seed));
+        grid.truncate(n);
+        grid
+    }
+}
+
+/// Cross-validated score of a candidate
+#[derive(Debug, Clone)]
+pub struct Trial {
+    pub params: TrainParams,
+    /// mean of the metric over the folds
+    pub score: Option<Float>,
+    /// standard deviation of the metric over the folds
+    pub spread: Option<Float>,
+}
+
+/// Scores all candidates by cross-validation, returns the trials best first
+pub fn search(
+    dall: &DMatrix,
+    qids: &[usize],
+    candidates: &[TrainParams],
+    k: usize,
+    seed: u64,
+    metric: &str,
+) -> Result<Vec<Trial>, Box<dyn Error>> {
+    let mut trials = Vec::<Trial>::new();
+    for (i, params) in candidates.iter().enumerate() {
+        eprintln!("Trial {}/{}: {:?}", i + 1, candidates.len(), params);
+        let cv = cross_validate_matrix(dall, qids, k, params, seed)?;
+        let (score, spread) = cv
+            .summary()
+            .into_iter()
+            .find(|(name, _)| *name == metric)
+            .ok_or_else(|| format!("Unknown metric: {}", metric))?
+            .1
+            .map_or((None, None), |(m, s)| (Some(m), Some(s)));
+        trials.push(Trial {
+            params: params.clone(),
+            score,
+            spread,
+        });
+    }
+    // undefined scores last
+    trials.sort_by(|a, b| {
+        let a = a.score.unwrap_or(Float::NEG_INFINITY);
+        let b = b.score.unwrap_or(Float::NEG_INFINITY);
+        b.partial_cmp(&a).unwrap()
+    });
+    Ok(trials)
+}
+
+/// Trials as CSV, best first
+pub fn trials_csv(trials: &[Trial]) -> String {
+    let mut csv = String::from(
+        "max_depth,eta,subsample,colsample_bytree,min_child_weight,rounds,score,spread\n",
+    );
+    for t in trials {
+        let p = &t.params;
+        csv += format!(
+            "{},{},{},{},{},{},{},{}\n",
+            p.max_depth,
+            p.eta,
+            p.subsample,
+            p.colsample_bytree,
+            p.min_child_weight,
+            p.rounds,
+            t.score.map_or(String::new(), |s| s.to_string()),
+            t.spread.map_or(String::new(), |s| s.to_string())
+        )
+        .as_str();
+    }
+    csv
+}
+
+/// Trials as text table, best first
+pub fn trials_table(trials: &[Trial]) -> String {
+    let mut table = format!(
+        "{:>6}{:>8}{:>10}{:>10}{:>10}{:>8}{:>10}{:>10}\n",
+        "depth", "eta", "subsample", "colsample", "min_child", "rounds", "score", "spread"
+    );
+    for t in trials {
+        let p = &t.params;
+        table += format!(
+            "{:>6}{:>8}{:>10}{:>10}{:>10}{:>8}{:>10}{:>10}\n",
+            p.max_depth,
+            p.eta,
+            p.subsample,
+            p.colsample_bytree,
+            p.min_child_weight,
+            p.rounds,
+            format_metric(t.score),
+            format_metric(t.spread)
+        )
+        .as_str();
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid() {
+        let space = SearchSpace {
+            max_depth: vec![4, 6, 8],
+            eta: vec![0.05, 0.1],
+            ..Default::default()
+        };
+        let grid = space.grid();
+        assert_eq!(grid.len(), 6);
+        assert_eq!(grid[1].max_depth, 4);
+        assert_eq!(grid[1].eta, 0.1);
+        assert_eq!(grid[0].rounds, TrainParams::default().rounds);
+        let sample = space.sample(4, 1);
+        assert_eq!(sample.len(), 4);
+        assert!(sample.iter().all(|p| grid.contains(p)));
+        assert_eq!(space.sample(10, 1).len(), 6);
+    }
+    #[test]
+    fn test_trials_csv() {
+        let trials = vec![Trial {
+            params: TrainParams::default(),
+            score: Some(0.9),
+            spread: None,
+        }];
+        assert!(trials_csv(&trials).ends_with("6,0.1,1,1,1,200,0.9,\n"));
+    }
+}
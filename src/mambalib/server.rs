@@ -0,0 +1,289 @@
+//! Local HTTP service for bond perception.
+//!
+//! The model is loaded once, requests are handled one after another. Invalid
+//! molecules give a 400, request bodies above `MAX_BODY_BYTES` a 413 and an
+//! internal panic a 500 without stopping the server:
+//!
+//! - `GET /health` returns `{"status":"ok"}`
+//! - `POST /perceive` takes a xyz file or a JSON molecule and returns SDF
+//! - `POST /batch` takes a multi-frame xyz file or a JSON array and returns
+//!   an SD file with several records
+//!
+//! Add `?format=json` to get JSON instead of SDF. A JSON molecule looks like
+//! `{"elements": ["O", "H", "H"], "coords": [[0, 0, 0], ...], "charge": 0, "multiplicity": 1}`.
+
+use std::error::Error;
+use std::io::Read;
+use std::panic::{self, AssertUnwindSafe};
+
+use ndarray::Array2;
+use serde::Deserialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+use xgboost::Booster;
+
+use crate::ml::predict_with_config;
+use crate::valence::postprocess;
+use crate::{
+    create_molblock, mol_from_string, mols_from_xyz_string, perceived_molecule, FeatureConfig,
+    Float, PerceivedMolecule, XYZMolecule,
+};
+
+/// Largest accepted request body
+pub const MAX_BODY_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Molecule posted as JSON
+#[derive(Debug, Deserialize)]
+pub struct MoleculeInput {
+    pub elements: Vec<String>,
+    pub coords: Vec<[Float; 3]>,
+    #[serde(default)]
+    pub charge: i32,
+    #[serde(default = "singlet")]
+    pub multiplicity: u32,
+}
+
+fn singlet() -> u32 {
+    1
+}
+
+impl MoleculeInput {
+    fn into_molecule(self) -> Result<XYZMolecule, Box<dyn Error>> {
+        if self.elements.len() != self.coords.len() {
+            return Err("elements and coords differ in length".into());
+        }
+        let flat: Vec<Float> = self.coords.iter().flatten().cloned().collect();
+        let coords = Array2::from_shape_vec((self.elements.len(), 3), flat)?;
+        let mut mol = XYZMolecule::new(self.elements, coords, self.charge);
+        mol.multiplicity = self.multiplicity;
+        Ok(mol)
+    }
+}
+
+/// Output of a single perception, either a molblock or the JSON structure
+enum Perceived {
+    Molblock(String),
+    Json(PerceivedMolecule),
+}
+
+fn perceive(
+    booster: &Booster,
+    config: &FeatureConfig,
+    mut mol: XYZMolecule,
+    json: bool,
+) -> Result<Perceived, Box<dyn Error>> {
+    let df = predict_with_config(booster, &mol, config)?;
+    let df = postprocess(&mut mol, df)?;
+    if json {
+        Ok(Perceived::Json(perceived_molecule(&mol, &df)?))
+    } else {
+        Ok(Perceived::Molblock(create_molblock(mol, df)?))
+    }
+}
+
+fn is_json(body: &str) -> bool {
+    let body = body.trim_start();
+    body.starts_with('{') || body.starts_with('[')
+}
+
+/// Molecules of the request body, xyz or JSON
+fn parse_body(body: &str, batch: bool) -> Result<Vec<XYZMolecule>, Box<dyn Error>> {
+    if is_json(body) {
+        let inputs: Vec<MoleculeInput> = if batch {
+            serde_json::from_str(body)?
+        } else {
+            vec![serde_json::from_str(body)?]
+        };
+        inputs.into_iter().map(|m| m.into_molecule()).collect()
+    } else if batch {
+        mols_from_xyz_string(body)
+    } else {
+        Ok(vec![mol_from_string(body)?])
+    }
+}
+
+/// Handles a single request, returns status code, content type and body
+pub fn handle(
+    booster: &Booster,
+    config: &FeatureConfig,
+    method: &Method,
+    url: &str,
+    body: &str,
+) -> (u16, &'static str, String) {
+    let (path, query) = match url.split_once('?') {
+        Some((path, query)) => (path, query),
+        None => (url, ""),
+    };
+    let json = query.split('&').any(|q| q == "format=json");
+    let batch = match (method, path) {
+        (Method::Get, "/health") => {
+            return (200, "application/json", "{\"status\":\"ok\"}".to_owned())
+        }
+        (Method::Post, "/perceive") => false,
+        (Method::Post, "/batch") => true,
+        _ => return (404, "text/plain", format!("Not found: {} {}", method, path)),
+    };
+    let result = parse_body(body, batch).and_then(|mols| {
+        mols.into_iter()
+            .map(|mol| perceive(booster, config, mol, json))
+            .collect::<Result<Vec<Perceived>, Box<dyn Error>>>()
+    });
+    let results = match result {
+        Ok(results) => results,
+        Err(e) => return (400, "text/plain", e.to_string()),
+    };
+    let mut molblocks = Vec::<String>::new();
+    let mut perceived = Vec::<PerceivedMolecule>::new();
+    for r in results {
+        match r {
+            Perceived::Molblock(m) => molblocks.push(m),
+            Perceived::Json(p) => perceived.push(p),
+        }
+    }
+    if json {
+        let out = if batch {
+            serde_json::to_string(&perceived)
+        } else {
+            serde_json::to_string(&perceived[0])
+        };
+        match out {
+            Ok(out) => (200, "application/json", out),
+            Err(e) => (500, "text/plain", e.to_string()),
+        }
+    } else if batch {
+        let out = molblocks.iter().map(|m| m.clone() + "$$$$\n").collect();
+        (200, "chemical/x-mdl-sdfile", out)
+    } else {
+        (200, "chemical/x-mdl-molfile", molblocks.remove(0))
+    }
+}
+
+fn respond(
+    booster: &Booster,
+    config: &FeatureConfig,
+    mut request: Request,
+) -> Result<(), Box<dyn Error>> {
+    let mut body = String::new();
+    let read = request
+        .as_reader()
+        .take(MAX_BODY_BYTES + 1)
+        .read_to_string(&mut body);
+    let (status, content_type, out) = match read {
+        Ok(n) if n as u64 > MAX_BODY_BYTES => (
+            413,
+            "text/plain",
+            format!("Request body exceeds {} bytes", MAX_BODY_BYTES),
+        ),
+        Ok(_) => {
+            let (method, url) = (request.method(), request.url());
+            // a bug for one input must not stop the server for all clients
+            panic::catch_unwind(AssertUnwindSafe(|| {
+                handle(booster, config, method, url, &body)
+            }))
+            .unwrap_or_else(|_| (500, "text/plain", "Internal error".to_owned()))
+        }
+        Err(e) => (400, "text/plain", e.to_string()),
+    };
+    eprintln!("{} {} -> {}", request.method(), request.url(), status);
+    let header = Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes()).unwrap();
+    request.respond(
+        Response::from_string(out)
+            .with_status_code(status)
+            .with_header(header),
+    )?;
+    Ok(())
+}
+
+/// Handles requests of a server, at most `max_requests` if given
+pub fn run(
+    server: &Server,
+    booster: &Booster,
+    config: &FeatureConfig,
+    max_requests: Option<usize>,
+) -> Result<(), Box<dyn Error>> {
+    let mut handled = 0;
+    for request in server.incoming_requests() {
+        if let Err(e) = respond(booster, config, request) {
+            eprintln!("Could not respond: {}", e);
+        }
+        handled += 1;
+        if max_requests.map_or(false, |m| handled >= m) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Serves bond perception on the given address until the process is stopped, `config`
+/// is the feature config the model was trained with
+pub fn serve(addr: &str, booster: &Booster, config: &FeatureConfig) -> Result<(), Box<dyn Error>> {
+    let server = Server::http(addr).map_err(|e| e.to_string())?;
+    eprintln!("Listening on http://{}", addr);
+    run(&server, booster, config, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::TcpStream;
+    use std::thread;
+
+    use super::*;
+    use crate::ml::load_model_with_config;
+
+    fn http(addr: std::net::SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn post(path: &str, body: &str) -> String {
+        format!(
+            "POST {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            path,
+            body.len(),
+            body
+        )
+    }
+
+    #[test]
+    fn test_server() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        let xyz = std::fs::read_to_string("data/test1.xyz").unwrap();
+        let client = thread::spawn(move || {
+            let health = http(
+                addr,
+                "GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            );
+            let molblock = http(addr, &post("/perceive", &xyz));
+            let json = http(addr, &post("/perceive?format=json", &xyz));
+            let batch = http(addr, &post("/batch", &(xyz.clone() + xyz.as_str())));
+            let bad = http(addr, &post("/perceive", "no molecule"));
+            // malformed inputs must not stop the server
+            let invalid = vec![
+                http(addr, &post("/perceive", "3\n\nC 0 0 0")),
+                http(addr, &post("/perceive", "1\n\nC 0 0 0")),
+                http(
+                    addr,
+                    "GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                ),
+            ];
+            (health, molblock, json, batch, bad, invalid)
+        });
+        let (booster, config) = load_model_with_config("xgb.model").unwrap();
+        run(&server, &booster, &config, Some(8)).unwrap();
+        let (health, molblock, json, batch, bad, invalid) = client.join().unwrap();
+        assert!(health.starts_with("HTTP/1.1 200"));
+        assert!(health.ends_with("{\"status\":\"ok\"}"));
+        assert!(molblock.contains("V2000"));
+        assert!(json.contains("\"bonds\""));
+        assert_eq!(batch.matches("$$$$").count(), 2);
+        assert!(bad.starts_with("HTTP/1.1 400"));
+        assert!(invalid[0].starts_with("HTTP/1.1 400"));
+        assert!(invalid[0].contains("Expected 3 atoms"));
+        assert!(invalid[1].starts_with("HTTP/1.1 400"));
+        assert!(invalid[2].starts_with("HTTP/1.1 200"));
+    }
+}
@@ -0,0 +1,289 @@
+//! Bond changes along a trajectory, e.g. to find reaction events in reactive MD.
+//!
+//! Bonds are perceived for every frame, an event is reported when a bond forms,
+//! breaks or changes its order. With a window larger than one, a new bond order
+//! has to persist for that many frames before it counts, which suppresses
+//! bonds flickering due to vibrations.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+
+use polars::prelude::*;
+use xgboost::Booster;
+
+use crate::ml::predict_with_config;
+use crate::valence::postprocess;
+use crate::{bond_orders, create_molblock, FeatureConfig, Float, XYZMolecule};
+
+/// Molecule of a single frame with its perceived bonds (zero based, `i < j`, order > 0)
+pub struct PerceivedFrame {
+    pub mol: XYZMolecule,
+    pub bonds: BTreeMap<(usize, usize), u32>,
+}
+
+/// Change of a bond between two frames, order 0 means no bond
+#[derive(Debug, Clone, PartialEq)]
+pub struct BondEvent {
+    /// first frame with the new bond order
+    pub frame: usize,
+    pub atom1: usize,
+    pub atom2: usize,
+    pub old_order: u32,
+    pub new_order: u32,
+}
+
+impl BondEvent {
+    pub fn kind(&self) -> &'static str {
+        match (self.old_order, self.new_order) {
+            (0, _) => "formed",
+            (_, 0) => "broken",
+            _ => "changed",
+        }
+    }
+}
+
+/// Perceives the bonds of all frames with an already loaded model and its feature config
+pub fn perceive_frames(
+    booster: &Booster,
+    config: &FeatureConfig,
+    mols: Vec<XYZMolecule>,
+) -> Result<Vec<PerceivedFrame>, Box<dyn Error>> {
+    let natoms = mols.first().map_or(0, |m| m.natoms);
+    let mut frames = Vec::<PerceivedFrame>::new();
+    for (i, mut mol) in mols.into_iter().enumerate() {
+        if mol.natoms != natoms {
+            return Err(
+                format!("Frame {} has {} atoms, expected {}", i, mol.natoms, natoms).into(),
+            );
+        }
+        let df = predict_with_config(booster, &mol, config)?;
+        let df = postprocess(&mut mol, df)?;
+        let bonds = bond_orders(&df)?
+            .into_iter()
+            .filter(|(_, _, order)| *order > 0)
+            .map(|(a, b, order)| ((a.min(b), a.max(b)), order))
+            .collect();
+        frames.push(PerceivedFrame { mol, bonds });
+    }
+    Ok(frames)
+}
+
+/// Bond events of the frames, a new order has to be stable for `window` frames
+pub fn bond_events(frames: &[PerceivedFrame], window: usize) -> Vec<BondEvent> {
+    let window = window.max(1);
+    let pairs: BTreeSet<(usize, usize)> = frames
+        .iter()
+        .flat_map(|f| f.bonds.keys().cloned())
+        .collect();
+    let mut events = Vec::<BondEvent>::new();
+    for (a, b) in pairs {
+        let orders: Vec<u32> = frames
+            .iter()
+            .map(|f| f.bonds.get(&(a, b)).cloned().unwrap_or(0))
+            .collect();
+        let mut current = orders[0];
+        let mut i = 1;
+        while i < orders.len() {
+            let order = orders[i];
+            let stable =
+                i + window <= orders.len() && orders[i..i + window].iter().all(|o| *o == order);
+            if order != current && stable {
+                events.push(BondEvent {
+                    frame: i,
+                    atom1: a,
+                    atom2: b,
+                    old_order: current,
+                    new_order: order,
+                });
+                current = order;
+                i += window;
+            } else {
+                i += 1;
+            }
+        }
+    }
+    events.sort_by_key(|e| (e.frame, e.atom1, e.atom2));
+    events
+}
+
+/// Events as CSV, atom indices are zero based
+pub fn events_csv(events: &[BondEvent]) -> String {
+    let mut csv = String::from("frame,atom1,atom2,old_order,new_order,event\n");
+    for e in events {
+        csv += format!(
+            "{},{},{},{},{},{}\n",
+            e.frame,
+            e.atom1,
+            e.atom2,
+            e.old_order,
+            e.new_order,
+            e.kind()
+        )
+        .as_str();
+    }
+    csv
+}
+
+/// Connected components of the frame containing one of the atoms, sorted atom indices
+fn fragments(frame: &PerceivedFrame, atoms: &BTreeSet<usize>) -> Vec<Vec<usize>> {
+    let natoms = frame.mol.natoms;
+    let mut neighbors = vec![Vec::<usize>::new(); natoms];
+    for (a, b) in frame.bonds.keys() {
+        neighbors[*a].push(*b);
+        neighbors[*b].push(*a);
+    }
+    let mut visited = vec![false; natoms];
+    let mut fragments = Vec::<Vec<usize>>::new();
+    for start in atoms {
+        if visited[*start] {
+            continue;
+        }
+        let mut fragment = Vec::<usize>::new();
+        let mut stack = vec![*start];
+        visited[*start] = true;
+        while let Some(i) = stack.pop() {
+            fragment.push(i);
+            for j in &neighbors[i] {
+                if !visited[*j] {
+                    visited[*j] = true;
+                    stack.push(*j);
+                }
+            }
+        }
+        fragment.sort_unstable();
+        fragments.push(fragment);
+    }
+    fragments
+}
+
+/// Molblock of a subset of atoms of the frame
+fn fragment_molblock(
+    frame: &PerceivedFrame,
+    atoms: &[usize],
+    name: String,
+) -> Result<String, Box<dyn Error>> {
+    let mol = &frame.mol;
+    let mut sub = XYZMolecule::new(
+        atoms.iter().map(|i| mol.atoms[*i].clone()).collect(),
+        mol.coords.select(ndarray::Axis(0), atoms),
+        0,
+    );
+    sub.name = name;
+    sub.charges = atoms.iter().map(|i| mol.charges[*i]).collect();
+    sub.radicals = atoms.iter().map(|i| mol.radicals[*i]).collect();
+    sub.q = sub.charges.iter().sum();
+
+    // bonds renumbered to the fragment, one based as in the feature table
+    let index = |i: &usize| atoms.binary_search(i).ok();
+    let mut id1 = Vec::<Float>::new();
+    let mut id2 = Vec::<Float>::new();
+    let mut preds = Vec::<Float>::new();
+    for ((a, b), order) in frame.bonds.iter() {
+        if let (Some(a), Some(b)) = (index(a), index(b)) {
+            id1.push((a + 1) as Float);
+            id2.push((b + 1) as Float);
+            preds.push(*order as Float);
+        }
+    }
+    let df = DataFrame::new(vec![
+        Series::new("id1", id1),
+        Series::new("id2", id2),
+        Series::new("preds", preds),
+    ])?;
+    create_molblock(sub, df)
+}
+
+/// SD file with the fragments containing changed bonds at each event frame
+pub fn changed_species_sdf(
+    frames: &[PerceivedFrame],
+    events: &[BondEvent],
+) -> Result<String, Box<dyn Error>> {
+    let mut changed = BTreeMap::<usize, BTreeSet<usize>>::new();
+    for e in events {
+        let atoms = changed.entry(e.frame).or_default();
+        atoms.insert(e.atom1);
+        atoms.insert(e.atom2);
+    }
+    let mut sdf = String::new();
+    for (frame, atoms) in changed {
+        for (k, fragment) in fragments(&frames[frame], &atoms).iter().enumerate() {
+            let name = format!(
+                "{} frame {} species {}",
+                frames[frame].mol.name,
+                frame,
+                k + 1
+            );
+            sdf += fragment_molblock(&frames[frame], fragment, name.trim().to_owned())?.as_str();
+            sdf += format!(">  <frame>\n{}\n\n$$$$\n", frame).as_str();
+        }
+    }
+    Ok(sdf)
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+
+    use super::*;
+
+    fn frame(bonds: &[((usize, usize), u32)]) -> PerceivedFrame {
+        let atoms = vec!["C", "O", "H", "H"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let mut mol = XYZMolecule::new(atoms, Array2::zeros((4, 3)), 0);
+        mol.charges = vec![0; 4];
+        mol.radicals = vec![0; 4];
+        PerceivedFrame {
+            mol,
+            bonds: bonds.iter().cloned().collect(),
+        }
+    }
+
+    #[test]
+    fn test_bond_events() {
+        let co = ((0, 1), 1);
+        let co2 = ((0, 1), 2);
+        let ch = ((0, 2), 1);
+        // C-H flickers in frame 2, C=O forms in frame 3 and persists
+        let frames = vec![
+            frame(&[co]),
+            frame(&[co]),
+            frame(&[co, ch]),
+            frame(&[co2]),
+            frame(&[co2]),
+            frame(&[co2]),
+        ];
+        let events = bond_events(&frames, 1);
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].kind(), "formed");
+        assert_eq!(events[1].kind(), "changed");
+        assert_eq!(events[2].kind(), "broken");
+
+        let events = bond_events(&frames, 2);
+        assert_eq!(
+            events,
+            vec![BondEvent {
+                frame: 3,
+                atom1: 0,
+                atom2: 1,
+                old_order: 1,
+                new_order: 2
+            }]
+        );
+        assert_eq!(
+            events_csv(&events),
+            "frame,atom1,atom2,old_order,new_order,event\n3,0,1,1,2,changed\n"
+        );
+    }
+    #[test]
+    fn test_changed_species() {
+        let frames = vec![frame(&[((0, 1), 1)]), frame(&[((0, 1), 1), ((0, 2), 1)])];
+        let events = bond_events(&frames, 1);
+        let sdf = changed_species_sdf(&frames, &events).unwrap();
+        assert_eq!(sdf.matches("$$$$").count(), 1);
+        // C, O and the new H, the other H is not part of the species
+        assert!(sdf.contains("3 2  0  0  0  0  0  0  0  0  1 V2000"));
+        assert!(sdf.contains(">  <frame>\n1\n"));
+    }
+}
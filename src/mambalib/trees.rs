@@ -0,0 +1,320 @@
+//! Pure Rust evaluation of a trained tree ensemble.
+//!
+//! Reads the binary model format written by `Booster::save` (gbtree booster, one
+//! tree per class and boosting round) and sums the leaf values of the trees of
+//! every class. This needs no libxgboost and is used where it is not available,
+//! e.g. on wasm32. The margins are the same as those of `Booster::predict_margin`
+//! on a `DMatrix::from_dense` table, which like the sparse libsvm training rows
+//! treats zeros as missing values.
+
+use std::convert::TryInto;
+use std::error::Error;
+
+use polars::prelude::*;
+
+use crate::utils::df2vec;
+use crate::{create_dataframe, FeatureConfig, Float, XYZMolecule};
+
+/// Split or leaf of a regression tree
+struct Node {
+    left: i32,
+    right: i32,
+    /// feature index, the highest bit marks missing values going left
+    split_index: u32,
+    /// split condition, or the leaf value of a leaf
+    value: Float,
+}
+
+/// Tree ensemble of a multi-class model
+pub struct TreeEnsemble {
+    base_score: Float,
+    num_features: usize,
+    num_classes: usize,
+    trees: Vec<Vec<Node>>,
+    /// class of every tree
+    tree_classes: Vec<usize>,
+}
+
+/// Sequential reader of the little endian model file
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        let end = self.pos + n;
+        if end > self.bytes.len() {
+            return Err("Truncated model file".into());
+        }
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn skip(&mut self, n: usize) -> Result<(), Box<dyn Error>> {
+        self.take(n).map(|_| ())
+    }
+
+    fn i32(&mut self) -> Result<i32, Box<dyn Error>> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, Box<dyn Error>> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, Box<dyn Error>> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, Box<dyn Error>> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn string(&mut self) -> Result<String, Box<dyn Error>> {
+        let len = self.u64()? as usize;
+        Ok(String::from_utf8(self.take(len)?.to_vec())?)
+    }
+}
+
+impl TreeEnsemble {
+    /// Parses a binary xgboost model
+    pub fn from_buffer(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut r = Reader { bytes, pos: 0 };
+        if bytes.starts_with(b"binf") {
+            r.skip(4)?;
+        }
+        // learner parameters: base score, features, classes, flags, version, reserved
+        let base_score = r.f32()?;
+        let num_features = r.u32()? as usize;
+        let num_classes = r.i32()?.max(1) as usize;
+        r.skip(4 * 4 + 27 * 4)?;
+        let objective = r.string()?;
+        let booster = r.string()?;
+        if booster != "gbtree" {
+            return Err(format!("Unsupported booster {}, expected gbtree", booster).into());
+        }
+        if !objective.starts_with("multi:") && num_classes > 1 {
+            return Err(format!("Unsupported objective {}", objective).into());
+        }
+        // gbtree parameters: trees, roots, features, padding, pbuffer, output groups, leaf vector
+        let num_trees = r.i32()? as usize;
+        r.skip(3 * 4 + 8 + 2 * 4 + 32 * 4)?;
+        let mut trees = Vec::with_capacity(num_trees);
+        for _ in 0..num_trees {
+            let num_roots = r.i32()?;
+            let num_nodes = r.i32()? as usize;
+            r.skip(3 * 4)?;
+            let leaf_vector = r.i32()?;
+            r.skip(31 * 4)?;
+            if num_roots != 1 || num_nodes == 0 {
+                return Err("Unsupported tree layout".into());
+            }
+            let mut nodes = Vec::with_capacity(num_nodes);
+            for _ in 0..num_nodes {
+                let _parent = r.i32()?;
+                let left = r.i32()?;
+                let right = r.i32()?;
+                let split_index = r.u32()?;
+                let value = r.f32()?;
+                let valid = |child: i32| child == -1 || (child > 0 && (child as usize) < num_nodes);
+                if !valid(left) || !valid(right) {
+                    return Err("Invalid node in model file".into());
+                }
+                nodes.push(Node {
+                    left,
+                    right,
+                    split_index,
+                    value,
+                });
+            }
+            // node statistics (loss change, hessian, weight, leaf count) are not needed
+            r.skip(num_nodes * 16)?;
+            if leaf_vector != 0 {
+                let len = r.u64()? as usize;
+                r.skip(len * 4)?;
+            }
+            trees.push(nodes);
+        }
+        let tree_classes = (0..num_trees)
+            .map(|_| r.i32().map(|c| c as usize))
+            .collect::<Result<Vec<usize>, _>>()?;
+        if tree_classes.iter().any(|c| *c >= num_classes) {
+            return Err("Invalid tree class in model file".into());
+        }
+        Ok(TreeEnsemble {
+            base_score,
+            num_features,
+            num_classes,
+            trees,
+            tree_classes,
+        })
+    }
+
+    pub fn num_classes(&self) -> usize {
+        self.num_classes
+    }
+
+    /// Leaf value of a tree for one row of features, zeros, NaN and the
+    /// columns after the end of the row take the default branch
+    fn leaf(nodes: &[Node], row: &[Float]) -> Float {
+        let mut node = &nodes[0];
+        while node.left != -1 {
+            let feature = (node.split_index & 0x7fff_ffff) as usize;
+            let value = row.get(feature).cloned().unwrap_or(Float::NAN);
+            let left = if value == 0.0 || value.is_nan() {
+                node.split_index >> 31 == 1
+            } else {
+                value < node.value
+            };
+            node = &nodes[if left { node.left } else { node.right } as usize];
+        }
+        node.value
+    }
+
+    /// Raw margins of all classes, row major, for a flat table with `ncols` columns,
+    /// missing columns at the end are missing values
+    pub fn predict_margin(
+        &self,
+        flat: &[Float],
+        ncols: usize,
+    ) -> Result<Vec<Float>, Box<dyn Error>> {
+        if ncols == 0 || ncols > self.num_features {
+            return Err(format!(
+                "The model needs up to {} features, got {}",
+                self.num_features, ncols
+            )
+            .into());
+        }
+        let mut margins = Vec::with_capacity(flat.len() / ncols.max(1) * self.num_classes);
+        for row in flat.chunks(ncols) {
+            let mut m = vec![self.base_score; self.num_classes];
+            for (nodes, class) in self.trees.iter().zip(&self.tree_classes) {
+                m[*class] += Self::leaf(nodes, row);
+            }
+            margins.extend(m);
+        }
+        Ok(margins)
+    }
+
+    /// Predicted class of every row, the class with the largest margin
+    pub fn predict(&self, flat: &[Float], ncols: usize) -> Result<Vec<Float>, Box<dyn Error>> {
+        let margins = self.predict_margin(flat, ncols)?;
+        Ok(margins
+            .chunks(self.num_classes)
+            .map(|m| {
+                let best = m
+                    .iter()
+                    .enumerate()
+                    .fold(0, |best, (k, x)| if *x > m[best] { k } else { best });
+                best as Float
+            })
+            .collect())
+    }
+}
+
+/// Softmax probability of the predicted class from the raw margins of all classes
+pub fn class_probabilities(margins: &[f32], preds: &[f32]) -> Vec<f32> {
+    let nclass = margins.len() / preds.len().max(1);
+    margins
+        .chunks(nclass)
+        .zip(preds.iter())
+        .map(|(m, p)| {
+            let max = m.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+            let norm: f32 = m.iter().map(|x| (x - max).exp()).sum();
+            (m[*p as usize] - max).exp() / norm
+        })
+        .collect()
+}
+
+/// Same as `ml::predict_with_config` with the pure Rust evaluator
+pub fn predict_with_trees(
+    trees: &TreeEnsemble,
+    mol: &XYZMolecule,
+    config: &FeatureConfig,
+) -> Result<DataFrame, Box<dyn Error>> {
+    let df = create_dataframe(mol, config)?;
+    let flat_vec = df2vec(&df);
+    let (_, ncols) = df.shape();
+    let preds = trees.predict(&flat_vec, ncols)?;
+    let margins = trees.predict_margin(&flat_vec, ncols)?;
+    let probs = class_probabilities(&margins, &preds);
+    let preds = Series::new("preds", preds);
+    let probs = Series::new("prob", probs);
+
+    Ok(df.hstack(&[preds, probs])?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_shipped_model() {
+        let trees = TreeEnsemble::from_buffer(&fs::read("xgb.model").unwrap()).unwrap();
+        assert_eq!(trees.num_classes(), 5);
+        assert_eq!(trees.num_features, 24);
+        assert_eq!(trees.trees.len(), 1000);
+        assert!(TreeEnsemble::from_buffer(&fs::read("xgb.model").unwrap()[..1000]).is_err());
+    }
+    #[test]
+    fn test_zero_is_missing() {
+        // one split on `atb` < 5.5 with missing values going right
+        let nodes = vec![
+            Node {
+                left: 1,
+                right: 2,
+                split_index: 4,
+                value: 5.5,
+            },
+            Node {
+                left: -1,
+                right: -1,
+                split_index: 0,
+                value: -1.0,
+            },
+            Node {
+                left: -1,
+                right: -1,
+                split_index: 0,
+                value: 1.0,
+            },
+        ];
+        let trees = TreeEnsemble {
+            base_score: 0.0,
+            num_features: 6,
+            num_classes: 1,
+            trees: vec![nodes],
+            tree_classes: vec![0],
+        };
+        // C-H pair of a neutral molecule, `q` and `atb` are zero, libxgboost goes right
+        let ch = [1.0, 2.0, 0.0, 5.0, 0.0, 1.09];
+        assert_eq!(trees.predict_margin(&ch, 6).unwrap(), vec![1.0]);
+        let cn = [1.0, 2.0, 0.0, 6.0, 5.0, 1.47];
+        assert_eq!(trees.predict_margin(&cn, 6).unwrap(), vec![-1.0]);
+        // rows shorter than the model, e.g. without neighbors, end in missing values
+        assert_eq!(trees.predict_margin(&cn[..4], 4).unwrap(), vec![1.0]);
+        assert!(trees.predict_margin(&[cn, cn].concat(), 12).is_err());
+    }
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn test_same_as_xgboost() {
+        let (booster, config) = crate::ml::load_model_with_config("xgb.model").unwrap();
+        let trees = TreeEnsemble::from_buffer(&fs::read("xgb.model").unwrap()).unwrap();
+        let mol = crate::mol_from_file("data/test1.xyz").unwrap();
+        let df = create_dataframe(&mol, &config).unwrap();
+        let flat_vec = df2vec(&df);
+        let dmat = xgboost::DMatrix::from_dense(&flat_vec, df.height()).unwrap();
+        let expected = booster.predict_margin(&dmat).unwrap();
+        let margins = trees.predict_margin(&flat_vec, df.width()).unwrap();
+        assert_eq!(margins.len(), expected.len());
+        for (m, e) in margins.iter().zip(&expected) {
+            assert!((m - e).abs() < 1e-3, "{} != {}", m, e);
+        }
+        let preds = booster.predict(&dmat).unwrap();
+        assert_eq!(trees.predict(&flat_vec, df.width()).unwrap(), preds);
+    }
+}
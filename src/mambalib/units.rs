@@ -0,0 +1,169 @@
+//! Length units of the input coordinates.
+//!
+//! The distance cut off and the distance features of the model assume Angstrom,
+//! coordinates in Bohr (e.g. from quantum chemistry codes) are converted before
+//! featurisation. Whether coordinates look like Bohr is guessed from the
+//! nearest neighbour distances, e.g. C-H is about 1.09 Angstrom but 2.06 Bohr.
+//! Only xyz coordinates are converted, the quantum chemistry readers already
+//! return Angstrom and mark the molecule with `XYZMolecule::angstrom`.
+
+use std::error::Error;
+use std::str::FromStr;
+
+use ndarray::Array2;
+
+use crate::{Float, XYZMolecule};
+
+/// Bohr to Angstrom conversion factor
+pub const BOHR: Float = 0.529_177_2;
+
+/// Nearest neighbour distance of hydrogens above which coordinates look like Bohr
+const H_NEIGHBOR_BOHR: Float = 1.5;
+/// Nearest neighbour distance of heavy atoms above which coordinates look like Bohr
+const HEAVY_NEIGHBOR_BOHR: Float = 2.4;
+
+/// Units of the input coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Units {
+    Angstrom,
+    Bohr,
+    /// Bohr if the nearest neighbour distances look like it, else Angstrom
+    Auto,
+}
+
+impl FromStr for Units {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "angstrom" | "ang" | "a" => Ok(Units::Angstrom),
+            "bohr" | "au" => Ok(Units::Bohr),
+            "auto" => Ok(Units::Auto),
+            _ => Err(format!("Unknown units: {}", s)),
+        }
+    }
+}
+
+/// Median of the nearest neighbour distances of the given atoms, non-finite distances are skipped
+fn median_nearest_neighbor(dm: &Array2<Float>, atoms: &[usize]) -> Option<Float> {
+    let mut nearest: Vec<Float> = atoms
+        .iter()
+        .filter_map(|&i| {
+            dm.row(i)
+                .iter()
+                .enumerate()
+                .filter(|(j, d)| *j != i && d.is_finite())
+                .map(|(_, d)| *d)
+                .fold(None, |min: Option<Float>, d| Some(min.map_or(d, |m| m.min(d))))
+        })
+        .collect();
+    if nearest.is_empty() {
+        return None;
+    }
+    nearest.sort_by(|a, b| a.total_cmp(b));
+    Some(nearest[nearest.len() / 2])
+}
+
+/// Heuristic whether the coordinates of the molecule are in Bohr
+///
+/// Uses the bonds to hydrogen if there are any, else the heavy atom bonds,
+/// which are less clear cut (e.g. S-S or Si-Si bonds).
+pub fn looks_like_bohr(mol: &XYZMolecule) -> Result<bool, Box<dyn Error>> {
+    if mol.natoms < 2 {
+        return Ok(false);
+    }
+    let dm = mol.distance_matrix()?;
+    let (hydrogens, heavy): (Vec<usize>, Vec<usize>) =
+        (0..mol.natoms).partition(|&i| mol.atoms[i] == "H");
+    if let Some(d) = median_nearest_neighbor(&dm, &hydrogens) {
+        return Ok(d > H_NEIGHBOR_BOHR);
+    }
+    Ok(median_nearest_neighbor(&dm, &heavy).map_or(false, |d| d > HEAVY_NEIGHBOR_BOHR))
+}
+
+/// Scales coordinates and lattice of a molecule from Bohr to Angstrom
+pub fn bohr_to_angstrom(mol: &mut XYZMolecule) {
+    mol.coords.mapv_inplace(|x| x * BOHR);
+    if let Some(lattice) = mol.lattice.as_mut() {
+        lattice.mapv_inplace(|x| x * BOHR);
+    }
+}
+
+/// Converts the molecule to Angstrom, returns whether it has been converted
+///
+/// For Angstrom input a warning is printed if the coordinates look like Bohr.
+/// Molecules already known to be in Angstrom are never converted.
+pub fn to_angstrom(mol: &mut XYZMolecule, units: Units) -> Result<bool, Box<dyn Error>> {
+    let name = if mol.name.is_empty() {
+        "the molecule"
+    } else {
+        &mol.name
+    };
+    if mol.angstrom {
+        if units == Units::Bohr {
+            eprintln!("Warning: {} is read in Angstrom, --units bohr is ignored", name);
+        }
+        return Ok(false);
+    }
+    let convert = match units {
+        Units::Bohr => true,
+        Units::Auto => looks_like_bohr(mol)?,
+        Units::Angstrom => {
+            if looks_like_bohr(mol)? {
+                eprintln!(
+                    "Warning: nearest neighbour distances of {} look like Bohr, consider --units bohr",
+                    name
+                );
+            }
+            false
+        }
+    };
+    if convert {
+        bohr_to_angstrom(mol);
+    }
+    Ok(convert)
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+
+    use super::*;
+
+    fn methane(scale: Float) -> XYZMolecule {
+        let atoms = ["C", "H", "H", "H", "H"].iter().map(|a| a.to_string()).collect();
+        let coords = arr2(&[
+            [0.0, 0.0, 0.0],
+            [0.629, 0.629, 0.629],
+            [-0.629, -0.629, 0.629],
+            [-0.629, 0.629, -0.629],
+            [0.629, -0.629, -0.629],
+        ]) * scale;
+        XYZMolecule::new(atoms, coords, 0)
+    }
+
+    #[test]
+    fn test_looks_like_bohr() {
+        assert!(!looks_like_bohr(&methane(1.0)).unwrap());
+        assert!(looks_like_bohr(&methane(1.0 / BOHR)).unwrap());
+        let atoms = vec!["C".to_string(), "C".to_string()];
+        let ethyne = XYZMolecule::new(atoms, arr2(&[[0.0, 0.0, 0.0], [0.0, 0.0, 1.2]]), 0);
+        assert!(!looks_like_bohr(&ethyne).unwrap());
+        let mut broken = methane(1.0);
+        broken.coords[[1, 0]] = Float::NAN;
+        assert!(!looks_like_bohr(&broken).unwrap());
+    }
+    #[test]
+    fn test_to_angstrom() {
+        let mut mol = methane(1.0 / BOHR);
+        assert!(to_angstrom(&mut mol, Units::Auto).unwrap());
+        assert!((mol.coords[[1, 0]] - 0.629).abs() < 1e-4);
+        assert!(!to_angstrom(&mut mol, Units::Auto).unwrap());
+        let mut qm = methane(1.0);
+        qm.angstrom = true;
+        assert!(!to_angstrom(&mut qm, Units::Bohr).unwrap());
+        assert!((qm.coords[[1, 0]] - 0.629).abs() < 1e-4);
+        assert_eq!("Bohr".parse::<Units>().unwrap(), Units::Bohr);
+        assert!("nm".parse::<Units>().is_err());
+    }
+}
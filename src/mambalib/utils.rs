@@ -8,7 +8,7 @@ use crate::Float;
 //https://rust-lang-nursery.github.io/rust-cookbook/science/mathematics/linear_algebra.html
 
 ///Distance of 2 vectors
-fn l2_dist(a: &ArrayView1<Float>, b: &ArrayView1<Float>) -> Float {
+pub fn l2_dist(a: &ArrayView1<Float>, b: &ArrayView1<Float>) -> Float {
     let diff = a - b;
     let res = (&diff * &diff).sum();
     return res.sqrt();
@@ -0,0 +1,242 @@
+//! Valence based post-processing of the predicted bond orders.
+
+use std::error::Error;
+
+use polars::prelude::*;
+
+use crate::{bond_orders, Float, XYZMolecule};
+
+/// Typical valence of main group elements
+pub fn default_valence(symbol: &str) -> Option<u32> {
+    match symbol {
+        "H" | "Li" | "Na" | "K" | "F" | "Cl" | "Br" | "I" => Some(1),
+        "Be" | "Mg" | "Ca" | "O" | "S" | "Se" => Some(2),
+        "B" | "Al" | "N" | "P" | "As" => Some(3),
+        "C" | "Si" | "Ge" => Some(4),
+        _ => None,
+    }
+}
+
+/// Contribution of a predicted bond class to the valence, class 4 is aromatic
+pub fn bond_valence(order: u32) -> Float {
+    match order {
+        4 => 1.5,
+        o => o as Float,
+    }
+}
+
+/// Sum of bond valences for each atom
+pub fn valence_sums(natoms: usize, bonds: &[(usize, usize, u32)]) -> Vec<Float> {
+    let mut sums = vec![0.0; natoms];
+    for (i, j, order) in bonds {
+        sums[*i] += bond_valence(*order);
+        sums[*j] += bond_valence(*order);
+    }
+    sums
+}
+
+/// Places the unpaired electrons given by the spin multiplicity.
+///
+/// Radicals go to the atoms with the largest open valence. If there are not
+/// enough open valences, a double or triple bond of the atom with the largest
+/// open valence is reduced, preferring the bond to the most over-bonded neighbor,
+/// e.g. triplet O2 ends up with a single bond and one radical per oxygen.
+/// Returns the dataframe with updated `preds`, the radicals are stored on the molecule.
+pub fn assign_radicals(
+    mol: &mut XYZMolecule,
+    mut df: DataFrame,
+) -> Result<DataFrame, Box<dyn Error>> {
+    let nrad = mol.multiplicity.saturating_sub(1);
+    mol.radicals = vec![0; mol.natoms];
+    if nrad == 0 {
+        return Ok(df);
+    }
+    let mut bonds = bond_orders(&df)?;
+    let mut placed = 0;
+    while placed < nrad {
+        let sums = valence_sums(mol.natoms, &bonds);
+        let free: Vec<Option<Float>> = (0..mol.natoms)
+            .map(|i| {
+                let valence = default_valence(&mol.atoms[i])? as Float;
+                Some(valence - sums[i] - mol.radicals[i] as Float)
+            })
+            .collect();
+        let open = free
+            .iter()
+            .enumerate()
+            .filter_map(|(i, f)| f.map(|f| (i, f)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        match open {
+            Some((i, f)) if f > 0.99 => {
+                mol.radicals[i] += 1;
+                placed += 1;
+            }
+            Some((_, most_open)) => {
+                // only bonds of the atom which is to get the radical
+                let reducible = bonds
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (_, _, o))| *o == 2 || *o == 3)
+                    .flat_map(|(k, (i, j, _))| [(k, *i, *j), (k, *j, *i)])
+                    .filter(|(_, a, _)| free[*a].map_or(false, |f| f > most_open - 0.01))
+                    .min_by(|x, y| {
+                        let partner = |b: &(usize, usize, usize)| free[b.2].unwrap_or(0.0);
+                        partner(x).total_cmp(&partner(y))
+                    });
+                match reducible {
+                    Some((k, _, _)) => bonds[k].2 -= 1,
+                    None => break,
+                }
+            }
+            None => break,
+        }
+    }
+    if placed < nrad {
+        eprintln!(
+            "Warning: could only place {} of {} unpaired electrons",
+            placed, nrad
+        );
+    }
+    let preds: Vec<Float> = bonds.iter().map(|(_, _, o)| *o as Float).collect();
+    df.with_column(Series::new("preds", preds))?;
+    Ok(df)
+}
+
+/// Formal charges of N, O, P and S from their bond valence.
+///
+/// Tetravalent N/P and trivalent O/S get +1, O/S with one and N/P with two bonds
+/// (and no radical) get -1. The charges are only kept if they add up to the total
+/// charge of the molecule, otherwise all formal charges are set to zero.
+pub fn assign_charges(mol: &mut XYZMolecule, df: &DataFrame) -> Result<(), Box<dyn Error>> {
+    let bonds = bond_orders(df)?;
+    let sums = valence_sums(mol.natoms, &bonds);
+    let radicals = if mol.radicals.len() == mol.natoms {
+        mol.radicals.clone()
+    } else {
+        vec![0; mol.natoms]
+    };
+    let mut charges = vec![0; mol.natoms];
+    for i in 0..mol.natoms {
+        let valence = match mol.atoms[i].as_str() {
+            "N" | "P" | "O" | "S" => default_valence(&mol.atoms[i]).unwrap() as Float,
+            _ => continue,
+        };
+        // no charges for isolated atoms or aromatic bonds with fractional valence
+        if sums[i] == 0.0 || sums[i].fract() != 0.0 {
+            continue;
+        }
+        let excess = (sums[i] + radicals[i] as Float - valence) as i32;
+        if excess == 1 || excess == -1 {
+            charges[i] = excess;
+        }
+    }
+    if charges.iter().sum::<i32>() == mol.q {
+        mol.charges = charges;
+    } else {
+        mol.charges = vec![0; mol.natoms];
+    }
+    Ok(())
+}
+
+/// Valence post-processing after prediction: radicals first, then formal charges
+pub fn postprocess(mol: &mut XYZMolecule, df: DataFrame) -> Result<DataFrame, Box<dyn Error>> {
+    let df = assign_radicals(mol, df)?;
+    assign_charges(mol, &df)?;
+    Ok(df)
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{arr2, Array2};
+
+    use super::*;
+
+    fn bond_df(id1: Vec<Float>, id2: Vec<Float>, preds: Vec<Float>) -> DataFrame {
+        DataFrame::new(vec![
+            Series::new("id1", id1),
+            Series::new("id2", id2),
+            Series::new("preds", preds),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_triplet_oxygen() {
+        let atoms = vec!["O".to_string(), "O".to_string()];
+        let coords = arr2(&[[0.0, 0.0, 0.0], [0.0, 0.0, 1.21]]);
+        let mut mol = XYZMolecule::new(atoms, coords, 0);
+        mol.multiplicity = 3;
+        let df = bond_df(vec![1.0], vec![2.0], vec![2.0]);
+        let df = assign_radicals(&mut mol, df).unwrap();
+        assert_eq!(bond_orders(&df).unwrap(), vec![(0, 1, 1)]);
+        assert_eq!(mol.radicals, vec![1, 1]);
+    }
+    #[test]
+    fn test_methyl_radical() {
+        let atoms = vec!["C", "H", "H", "H"].iter().map(|s| s.to_string()).collect();
+        let coords = arr2(&[
+            [0.0, 0.0, 0.0],
+            [1.08, 0.0, 0.0],
+            [-0.54, 0.94, 0.0],
+            [-0.54, -0.94, 0.0],
+        ]);
+        let mut mol = XYZMolecule::new(atoms, coords, 0);
+        mol.multiplicity = 2;
+        let df = bond_df(
+            vec![1.0, 1.0, 1.0, 2.0, 2.0, 3.0],
+            vec![2.0, 3.0, 4.0, 3.0, 4.0, 4.0],
+            vec![1.0, 1.0, 1.0, 0.0, 0.0, 0.0],
+        );
+        assign_radicals(&mut mol, df).unwrap();
+        assert_eq!(mol.radicals, vec![1, 0, 0, 0]);
+    }
+    #[test]
+    fn test_allyl_with_carbonyl() {
+        // allyl radical predicted as H2C=C=CH2 next to formaldehyde, the C=O stays
+        let atoms = ["C", "C", "C", "C", "O", "H", "H", "H", "H", "H", "H", "H"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let mut mol = XYZMolecule::new(atoms, Array2::zeros((12, 3)), 0);
+        mol.multiplicity = 2;
+        let df = bond_df(
+            vec![4.0, 1.0, 2.0, 1.0, 1.0, 2.0, 3.0, 3.0, 4.0, 4.0],
+            vec![5.0, 2.0, 3.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0, 12.0],
+            vec![2.0, 2.0, 2.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0],
+        );
+        let df = assign_radicals(&mut mol, df).unwrap();
+        let orders: Vec<u32> = bond_orders(&df).unwrap().iter().map(|b| b.2).collect();
+        assert_eq!(orders[..3], [2, 1, 2]);
+        assert_eq!(mol.radicals[..5], [1, 0, 0, 0, 0]);
+    }
+    #[test]
+    fn test_nitro_charges() {
+        // CH3-NO2, hydrogens left out
+        let atoms = vec!["C", "N", "O", "O"].iter().map(|s| s.to_string()).collect();
+        let coords = arr2(&[
+            [0.0, 0.0, 0.0],
+            [1.49, 0.0, 0.0],
+            [2.1, 1.07, 0.0],
+            [2.1, -1.07, 0.0],
+        ]);
+        let mut mol = XYZMolecule::new(atoms, coords, 0);
+        let df = bond_df(vec![2.0, 3.0, 4.0], vec![1.0, 2.0, 2.0], vec![1.0, 2.0, 1.0]);
+        postprocess(&mut mol, df).unwrap();
+        assert_eq!(mol.charges, vec![0, 1, 0, -1]);
+        // inconsistent with the total charge
+        mol.q = 1;
+        let df = bond_df(vec![2.0, 3.0, 4.0], vec![1.0, 2.0, 2.0], vec![1.0, 2.0, 1.0]);
+        postprocess(&mut mol, df).unwrap();
+        assert_eq!(mol.charges, vec![0, 0, 0, 0]);
+    }
+    #[test]
+    fn test_singlet() {
+        let atoms = vec!["C".to_string(), "O".to_string()];
+        let coords = arr2(&[[0.0, 0.0, 0.0], [0.0, 0.0, 1.2]]);
+        let mut mol = XYZMolecule::new(atoms, coords, 0);
+        let df = bond_df(vec![2.0], vec![1.0], vec![2.0]);
+        let df = assign_radicals(&mut mol, df).unwrap();
+        assert_eq!(bond_orders(&df).unwrap(), vec![(1, 0, 2)]);
+        assert_eq!(mol.radicals, vec![0, 0]);
+    }
+}
@@ -0,0 +1,261 @@
+//! Sanity checks of the input geometry before prediction.
+//!
+//! The model happily predicts bonds for any input, so broken geometries (clashing
+//! or duplicated atoms, NaN coordinates, unknown elements) would end up as
+//! confident-looking SDFs. Each check gives issues, which the caller reports as
+//! warnings or turns into errors.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use ndarray::Axis;
+
+use crate::elements::covalent_radius;
+use crate::{Float, XYZMolecule};
+
+/// Atoms closer than this in Angstrom are duplicates rather than a clash
+const DUPLICATE_DIST: Float = 0.01;
+/// Geometries with all atoms within this distance in Angstrom of their center are collapsed
+const COLLAPSE_RADIUS: Float = 0.5;
+
+/// Kinds of checks on the input geometry
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Check {
+    /// atoms closer than a fraction of their covalent radii sum
+    Clash,
+    /// atoms at the same position
+    Duplicate,
+    /// NaN or infinite coordinates
+    NonFinite,
+    /// element symbols which are not in the periodic table
+    UnknownElement,
+    /// all atoms (nearly) at one point
+    Collapsed,
+}
+
+/// All checks, in the order they are run
+pub static ALL_CHECKS: &[Check] = &[
+    Check::UnknownElement,
+    Check::NonFinite,
+    Check::Duplicate,
+    Check::Clash,
+    Check::Collapsed,
+];
+
+impl Check {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Check::Clash => "clash",
+            Check::Duplicate => "duplicate",
+            Check::NonFinite => "nonfinite",
+            Check::UnknownElement => "element",
+            Check::Collapsed => "collapsed",
+        }
+    }
+}
+
+impl FromStr for Check {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ALL_CHECKS
+            .iter()
+            .find(|c| c.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("Unknown check: {}", s))
+    }
+}
+
+/// Problem found by a check, atom indices are zero based
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    pub check: Check,
+    pub atoms: Vec<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {}", self.check.name(), self.message)
+    }
+}
+
+/// Settings of the validation
+pub struct Validation {
+    /// clash if closer than this fraction of the covalent radii sum
+    pub clash_factor: Float,
+    /// checks which make the validation fail instead of giving warnings,
+    /// non-finite coordinates always fail
+    pub fail_on: Vec<Check>,
+}
+
+impl Default for Validation {
+    fn default() -> Self {
+        Validation {
+            clash_factor: 0.5,
+            fail_on: Vec::new(),
+        }
+    }
+}
+
+/// Runs all checks on the molecule
+pub fn check_molecule(
+    mol: &XYZMolecule,
+    clash_factor: Float,
+) -> Result<Vec<Issue>, Box<dyn Error>> {
+    let mut issues = Vec::<Issue>::new();
+    for (i, symbol) in mol.atoms.iter().enumerate() {
+        if mol.elements[i].is_none() {
+            issues.push(Issue {
+                check: Check::UnknownElement,
+                atoms: vec![i],
+                message: format!("unknown element {} of atom {}", symbol, i + 1),
+            });
+        }
+    }
+    let nonfinite: Vec<usize> = (0..mol.natoms)
+        .filter(|&i| mol.coords.row(i).iter().any(|x| !x.is_finite()))
+        .collect();
+    if !nonfinite.is_empty() {
+        let ids: Vec<String> = nonfinite.iter().map(|i| (i + 1).to_string()).collect();
+        issues.push(Issue {
+            check: Check::NonFinite,
+            atoms: nonfinite,
+            message: format!("non-finite coordinates of atoms {}", ids.join(",")),
+        });
+        // distances are meaningless from here on
+        return Ok(issues);
+    }
+
+    let dm = mol.distance_matrix()?;
+    for i in 0..mol.natoms {
+        for j in i + 1..mol.natoms {
+            let dist = dm[[i, j]];
+            if dist < DUPLICATE_DIST {
+                issues.push(Issue {
+                    check: Check::Duplicate,
+                    atoms: vec![i, j],
+                    message: format!("atoms {} and {} are at the same position", i + 1, j + 1),
+                });
+                continue;
+            }
+            let (ri, rj) = match (
+                covalent_radius(&mol.atoms[i]),
+                covalent_radius(&mol.atoms[j]),
+            ) {
+                (Some(ri), Some(rj)) => (ri, rj),
+                _ => continue,
+            };
+            if dist < clash_factor * (ri + rj) {
+                issues.push(Issue {
+                    check: Check::Clash,
+                    atoms: vec![i, j],
+                    message: format!(
+                        "atoms {} ({}) and {} ({}) clash at {:.3} A",
+                        i + 1,
+                        mol.atoms[i],
+                        j + 1,
+                        mol.atoms[j],
+                        dist
+                    ),
+                });
+            }
+        }
+    }
+
+    if mol.natoms > 1 && mol.lattice.is_none() {
+        let center = mol.coords.mean_axis(Axis(0)).unwrap();
+        let radius = mol
+            .coords
+            .outer_iter()
+            .map(|c| (&c - &center).mapv(|x| x * x).sum().sqrt())
+            .fold(0.0, Float::max);
+        if radius < COLLAPSE_RADIUS {
+            issues.push(Issue {
+                check: Check::Collapsed,
+                atoms: (0..mol.natoms).collect(),
+                message: format!("all atoms within {:.3} A of their center", radius),
+            });
+        }
+    }
+    Ok(issues)
+}
+
+/// Checks the molecule, fails if an issue of a `fail_on` check or non-finite
+/// coordinates are found (nothing can be predicted from those), else returns
+/// the remaining issues to be reported as warnings
+pub fn validate(mol: &XYZMolecule, validation: &Validation) -> Result<Vec<Issue>, Box<dyn Error>> {
+    let issues = check_molecule(mol, validation.clash_factor)?;
+    let errors: Vec<String> = issues
+        .iter()
+        .filter(|issue| {
+            issue.check == Check::NonFinite || validation.fail_on.contains(&issue.check)
+        })
+        .map(|issue| issue.to_string())
+        .collect();
+    if !errors.is_empty() {
+        return Err(format!("Invalid geometry:\n{}", errors.join("\n")).into());
+    }
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+
+    use super::*;
+
+    fn mol(atoms: &[&str], coords: Vec<[Float; 3]>) -> XYZMolecule {
+        let atoms = atoms.iter().map(|a| a.to_string()).collect();
+        XYZMolecule::new(atoms, arr2(&coords), 0)
+    }
+
+    #[test]
+    fn test_valid() {
+        let water = mol(
+            &["O", "H", "H"],
+            vec![[0.0, 0.0, 0.0], [0.96, 0.0, 0.0], [-0.24, 0.93, 0.0]],
+        );
+        assert!(check_molecule(&water, 0.5).unwrap().is_empty());
+    }
+    #[test]
+    fn test_issues() {
+        let bad = mol(
+            &["C", "C", "Xx", "O"],
+            vec![
+                [0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0],
+                [1.5, 0.0, 0.0],
+                [1.8, 0.0, 0.0],
+            ],
+        );
+        let issues = check_molecule(&bad, 0.5).unwrap();
+        let checks: Vec<Check> = issues.iter().map(|i| i.check).collect();
+        assert_eq!(checks, vec![Check::UnknownElement, Check::Duplicate]);
+        let clash = mol(&["C", "O"], vec![[0.0, 0.0, 0.0], [0.0, 0.0, 0.6]]);
+        let issues = check_molecule(&clash, 0.5).unwrap();
+        assert_eq!(issues[0].check, Check::Clash);
+        assert_eq!(issues[1].check, Check::Collapsed);
+        let nan = mol(&["C", "O"], vec![[0.0, 0.0, 0.0], [0.0, Float::NAN, 1.2]]);
+        let issues = check_molecule(&nan, 0.5).unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].atoms, vec![1]);
+    }
+    #[test]
+    fn test_fail_on() {
+        let clash = mol(&["C", "O"], vec![[0.0, 0.0, 0.0], [0.0, 0.0, 0.6]]);
+        assert_eq!(validate(&clash, &Validation::default()).unwrap().len(), 2);
+        let validation = Validation {
+            fail_on: vec![Check::Clash],
+            ..Default::default()
+        };
+        assert!(validate(&clash, &validation).is_err());
+        let inf = mol(
+            &["C", "O"],
+            vec![[0.0, 0.0, 0.0], [0.0, 0.0, Float::INFINITY]],
+        );
+        assert!(validate(&inf, &Validation::default()).is_err());
+        assert_eq!("element".parse::<Check>().unwrap(), Check::UnknownElement);
+    }
+}
@@ -0,0 +1,80 @@
+//! WebAssembly bindings, enabled with the `wasm` feature.
+//!
+//! The model is embedded in the module, nothing is read from or written to disk.
+//! libxgboost is not available on wasm32, the trees of the model are evaluated by
+//! the `trees` module. Build e.g. with `wasm-pack build --target web -- --features wasm`
+//! (or check with `cargo build --lib --target wasm32-unknown-unknown --features wasm`),
+//! then in JavaScript
+//!
+//! ```js
+//! import init, { molblock_from_xyz } from "./pkg/mambalib.js";
+//! await init();
+//! const molblock = molblock_from_xyz(xyzText);
+//! ```
+
+use std::error::Error;
+
+use polars::prelude::DataFrame;
+use wasm_bindgen::prelude::*;
+
+use crate::metadata::ModelMetadata;
+use crate::trees::{predict_with_trees, TreeEnsemble};
+use crate::valence::postprocess;
+use crate::{create_json, create_molblock, mol_from_xyz_string, FeatureConfig, XYZMolecule};
+
+static MODEL_FILE: &[u8] = include_bytes!("../../xgb.model");
+static METADATA_FILE: &str = include_str!("../../xgb.model.json");
+
+/// Embedded model with the feature config of its metadata
+struct Model {
+    trees: TreeEnsemble,
+    config: FeatureConfig,
+}
+
+impl Model {
+    fn load() -> Result<Self, Box<dyn Error>> {
+        let metadata: ModelMetadata = serde_json::from_str(METADATA_FILE)?;
+        let mismatches = metadata.mismatches();
+        if !mismatches.is_empty() {
+            return Err(format!("Embedded model: {}", mismatches.join("; ")).into());
+        }
+        Ok(Model {
+            trees: TreeEnsemble::from_buffer(MODEL_FILE)?,
+            config: metadata.config,
+        })
+    }
+
+    /// Predicted and post-processed bonds
+    fn perceive(&self, mol: &mut XYZMolecule) -> Result<DataFrame, Box<dyn Error>> {
+        let df = predict_with_trees(&self.trees, mol, &self.config)?;
+        postprocess(mol, df)
+    }
+}
+
+thread_local! {
+    static MODEL: Model = Model::load().expect("Invalid embedded model");
+}
+
+/// Molblock with perceived bonds for the contents of a xyz file
+#[wasm_bindgen]
+pub fn molblock_from_xyz(contents: &str) -> Result<String, JsValue> {
+    MODEL
+        .with(|model| {
+            let mut mol = mol_from_xyz_string(contents)?;
+            let df = model.perceive(&mut mol)?;
+            create_molblock(mol, df)
+        })
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// JSON with atoms and perceived bonds for the contents of a xyz file
+#[wasm_bindgen]
+pub fn json_from_xyz(contents: &str) -> Result<String, JsValue> {
+    MODEL
+        .with(|model| {
+            let mut mol = mol_from_xyz_string(contents)?;
+            let df = model.perceive(&mut mol)?;
+            create_json(&mol, &df)
+        })
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
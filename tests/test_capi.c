@@ -0,0 +1,74 @@
+/*
+ * Test of the C API against the generated header, run from the repository root:
+ *
+ *   cargo build --release --lib --features capi
+ *   cc tests/test_capi.c -Iinclude -Ltarget/release -lmambalib -o target/test_capi
+ *   LD_LIBRARY_PATH=target/release target/test_capi
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "mamba.h"
+
+#define CHECK(cond)                                                          \
+    do {                                                                     \
+        if (!(cond)) {                                                       \
+            fprintf(stderr, "%s:%d: %s failed: %s\n", __FILE__, __LINE__,   \
+                    #cond, mamba_last_error());                              \
+            exit(1);                                                         \
+        }                                                                    \
+    } while (0)
+
+/* ethene */
+#define NATOMS 6
+static const char *ELEMENTS[NATOMS] = {"C", "C", "H", "H", "H", "H"};
+static const float COORDS[3 * NATOMS] = {
+    0.0f,  0.0f,   0.0f, 1.34f, 0.0f,  0.0f, -0.5f, 0.93f, 0.0f,
+    -0.5f, -0.93f, 0.0f, 1.84f, 0.93f, 0.0f, 1.84f, -0.93f, 0.0f,
+};
+
+int main(void) {
+    CHECK(mamba_predictor_new(NULL) == NULL);
+    CHECK(mamba_predictor_new("no_such.model") == NULL);
+    MambaPredictor *predictor = mamba_predictor_new("xgb.model");
+    CHECK(predictor != NULL);
+
+    MambaResult *result = mamba_perceive(predictor, ELEMENTS, COORDS, NATOMS, 0, 1);
+    CHECK(result != NULL);
+    size_t nbonds = mamba_result_num_bonds(result);
+    CHECK(nbonds == 5);
+    uint32_t atoms[2 * 5], orders[5];
+    CHECK(mamba_result_bonds(result, atoms, orders) == 0);
+    int double_bonds = 0;
+    for (size_t k = 0; k < nbonds; k++) {
+        if (atoms[2 * k] == 0 && atoms[2 * k + 1] == 1) {
+            CHECK(orders[k] == 2);
+            double_bonds++;
+        }
+    }
+    CHECK(double_bonds == 1);
+    int32_t charges[NATOMS];
+    uint32_t radicals[NATOMS];
+    CHECK(mamba_result_formal_charges(result, charges) == 0);
+    CHECK(mamba_result_radicals(result, radicals) == 0);
+    for (int i = 0; i < NATOMS; i++) {
+        CHECK(charges[i] == 0 && radicals[i] == 0);
+    }
+
+    /* NULL pointers are errors, not crashes */
+    const char *missing[NATOMS] = {"C", "C", "H", NULL, "H", "H"};
+    CHECK(mamba_perceive(predictor, missing, COORDS, NATOMS, 0, 1) == NULL);
+    CHECK(mamba_perceive(NULL, ELEMENTS, COORDS, NATOMS, 0, 1) == NULL);
+    CHECK(mamba_result_bonds(result, NULL, orders) == -1);
+    CHECK(mamba_result_formal_charges(NULL, charges) == -1);
+    CHECK(mamba_result_radicals(result, NULL) == -1);
+    CHECK(mamba_result_num_bonds(NULL) == 0);
+
+    mamba_result_free(result);
+    mamba_predictor_free(predictor);
+    mamba_result_free(NULL);
+    mamba_predictor_free(NULL);
+    printf("C API ok\n");
+    return 0;
+}
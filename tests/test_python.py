@@ -0,0 +1,51 @@
+"""Tests of the python bindings, run with `maturin develop && pytest tests` from the repository root."""
+
+import numpy as np
+import pytest
+
+import mambalib
+
+# carbon monoxide and ethene
+CO = (["C", "O"], [[0.0, 0.0, 0.0], [0.0, 0.0, 1.13]])
+ETHENE = (
+    ["C", "C", "H", "H", "H", "H"],
+    [
+        [0.0, 0.0, 0.0],
+        [1.34, 0.0, 0.0],
+        [-0.5, 0.93, 0.0],
+        [-0.5, -0.93, 0.0],
+        [1.84, 0.93, 0.0],
+        [1.84, -0.93, 0.0],
+    ],
+)
+
+
+@pytest.fixture(scope="module")
+def predictor():
+    return mambalib.Predictor("xgb.model")
+
+
+@pytest.mark.parametrize("dtype", [np.float64, np.float32])
+def test_perceive_dtypes(predictor, dtype):
+    elements, coords = ETHENE
+    result = predictor.perceive(elements, np.array(coords, dtype=dtype))
+    assert result["bonds"].shape == (5, 2)
+    assert "V2000" in result["molblock"]
+
+
+def test_perceive_list(predictor):
+    elements, coords = ETHENE
+    result = predictor.perceive(elements, coords)
+    assert len(result["orders"]) == 5
+
+
+def test_features_float64():
+    elements, coords = CO
+    table, names = mambalib.features(elements, np.array(coords, dtype=np.float64))
+    assert table.shape == (1, len(names))
+    assert table[0, names.index("distab")] == pytest.approx(1.13, abs=1e-5)
+
+
+def test_wrong_shape(predictor):
+    with pytest.raises(ValueError):
+        predictor.perceive(["C", "O"], np.zeros((3, 3)))